@@ -0,0 +1,72 @@
+// Extracting 50,000 BED regions from five 10 Mb contigs with one thread and
+// with several, to measure what --threads gains.
+
+use std::{fmt::Write as _, fs, hint::black_box};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use extract::{Coordinates, ExtractOptions, RegionFormat, Sequences};
+use rand::{rngs::StdRng, RngExt, SeedableRng};
+
+const CONTIGS: usize = 5;
+const CONTIG_LENGTH: usize = 10_000_000;
+const REGIONS: usize = 50_000;
+
+fn extract_regions(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let fasta = dir.path().join("ref.fa");
+    let bed = dir.path().join("regions.bed");
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let mut contents = String::new();
+    for contig in 0..CONTIGS {
+        writeln!(contents, ">chr{contig}").unwrap();
+        let bases: Vec<u8> = (0..CONTIG_LENGTH)
+            .map(|_| b"ACGT"[rng.random_range(0..4)])
+            .collect();
+        for line in bases.chunks(60) {
+            contents.push_str(std::str::from_utf8(line).unwrap());
+            contents.push('\n');
+        }
+    }
+    fs::write(&fasta, contents).unwrap();
+
+    let mut regions = String::new();
+    for _ in 0..REGIONS {
+        let contig = rng.random_range(0..CONTIGS);
+        let start = rng.random_range(0..CONTIG_LENGTH - 1000);
+        let length = rng.random_range(100..1000);
+        writeln!(regions, "chr{contig}\t{start}\t{}", start + length).unwrap();
+    }
+    fs::write(&bed, regions).unwrap();
+
+    let fasta = fasta.to_str().unwrap();
+    let bed = [bed.to_str().unwrap().to_string()];
+    Sequences::index_fasta(fasta).unwrap();
+
+    let mut group = c.benchmark_group("extract 50k regions");
+    group.sample_size(10);
+    for threads in [1, 4] {
+        group.bench_function(format!("{threads} threads"), |b| {
+            b.iter(|| {
+                let mut sequences = Sequences::new(
+                    fasta,
+                    &bed,
+                    Some(RegionFormat::Bed),
+                    Coordinates::default(),
+                    false,
+                )
+                .unwrap();
+                let options = ExtractOptions {
+                    threads,
+                    ..Default::default()
+                };
+                sequences.extract(options).unwrap();
+                black_box(sequences.records().count())
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, extract_regions);
+criterion_main!(benches);
@@ -0,0 +1,218 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+};
+
+use anyhow::{Context, Result};
+use noodles::{
+    bam,
+    core::{Position, Region},
+    sam::{
+        self,
+        alignment::Record,
+        record::cigar::{op::Kind, Op},
+    },
+};
+
+// Read a region for each mapped alignment in a SAM or BAM file (BAM if it
+// starts with the gzip magic bytes, since BAM is BGZF-compressed), covering
+// the reference the alignment spans, and named by the read's name. A BAM
+// record's CIGAR is read from its CG tag if it has too many operations for
+// the record itself, so long reads span all of the reference they cover.
+// Soft-clipped bases don't cover the reference, so they're added as flanks
+// on either side if requested (stopping at the start of the contig), e.g.,
+// to see the reference a clipped read might have come from. Regions are on
+// the forward strand, as the reference is.
+pub fn read_alignments(
+    path: &str,
+    soft_clips: bool,
+) -> Result<Vec<(Region, bool, Option<String>)>> {
+    let mut reader =
+        BufReader::new(File::open(path).with_context(|| format!("could not open {path}"))?);
+    let regions = if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        let mut reader = bam::Reader::new(reader);
+        let header = reader.read_header()?;
+        alignment_regions(&header, reader.records(&header), soft_clips)
+    } else {
+        let mut reader = sam::Reader::new(reader);
+        let header = reader.read_header()?;
+        alignment_regions(&header, reader.records(&header), soft_clips)
+    };
+    regions.with_context(|| format!("could not read {path}"))
+}
+
+// The regions of the mapped alignments among records. Unmapped reads, whose
+// position (if any) is only where they sort, and alignments with no CIGAR
+// are skipped.
+fn alignment_regions(
+    header: &sam::Header,
+    records: impl Iterator<Item = io::Result<Record>>,
+    soft_clips: bool,
+) -> Result<Vec<(Region, bool, Option<String>)>> {
+    let mut regions = Vec::new();
+    for record in records {
+        let record = record?;
+        let (Some(reference), Some(start)) =
+            (record.reference_sequence(header), record.alignment_start())
+        else {
+            continue;
+        };
+        if record.flags().is_unmapped() || record.cigar().is_empty() {
+            continue;
+        }
+        let (contig, _) = reference?;
+
+        let (left, right) = if soft_clips {
+            soft_clipped(&record)
+        } else {
+            (0, 0)
+        };
+        let start = usize::from(start);
+        let end = start + record.alignment_span() + right - 1;
+        let start = start.saturating_sub(left).max(1);
+        if end < start {
+            continue;
+        }
+        let interval = Position::try_from(start)?..=Position::try_from(end)?;
+        regions.push((
+            Region::new(contig.to_string(), interval),
+            false,
+            record.read_name().map(|name| name.to_string()),
+        ));
+    }
+    Ok(regions)
+}
+
+// The lengths of the soft clips at the start and end of an alignment, which
+// are inside any hard clips.
+fn soft_clipped(record: &Record) -> (usize, usize) {
+    let ops: Vec<_> = record
+        .cigar()
+        .iter()
+        .filter(|op| op.kind() != Kind::HardClip)
+        .collect();
+    let clipped = |op: Option<&&Op>| {
+        op.filter(|op| op.kind() == Kind::SoftClip)
+            .map_or(0, |op| op.len())
+    };
+    match &ops[..] {
+        [] => (0, 0),
+        [op] => (clipped(Some(op)), 0),
+        [first, .., last] => (clipped(Some(first)), clipped(Some(last))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, num::NonZeroUsize};
+
+    use noodles::sam::{
+        header::record::value::{map::ReferenceSequence, Map},
+        record::{Flags, ReadName},
+    };
+
+    use super::*;
+    use crate::{ExtractOptions, Sequences};
+
+    fn record(name: &str, flags: Flags, start: usize, cigar: &str) -> Record {
+        Record::builder()
+            .set_read_name(name.parse::<ReadName>().unwrap())
+            .set_flags(flags)
+            .set_reference_sequence_id(0)
+            .set_alignment_start(Position::try_from(start).unwrap())
+            .set_cigar(cigar.parse().unwrap())
+            .build()
+    }
+
+    #[test]
+    fn bam_alignments_span_their_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let reference = "ACGT".repeat(20_000);
+        let fasta = dir.path().join("ref.fa");
+        fs::write(&fasta, format!(">chr1\n{reference}\n")).unwrap();
+        let fasta = fasta.to_string_lossy();
+
+        let header = sam::Header::builder()
+            .add_reference_sequence(
+                "chr1".parse().unwrap(),
+                Map::<ReferenceSequence>::new(NonZeroUsize::new(reference.len()).unwrap()),
+            )
+            .build();
+        // More CIGAR operations than a BAM record holds, so they're written
+        // to its CG tag.
+        let long_cigar = "1M1D".repeat(40_000);
+        let records = [
+            record("r1", Flags::empty(), 10, "5M"),
+            record("r2", Flags::REVERSE_COMPLEMENTED, 20, "2S4M1D3M2I2M3S"),
+            record("r3", Flags::UNMAPPED, 30, "5M"),
+            record("r4", Flags::SECONDARY, 100, "3H10M5N5M1H"),
+            record("long", Flags::empty(), 1, &long_cigar),
+        ];
+        let bam = dir.path().join("aln.bam");
+        let mut writer = bam::Writer::new(File::create(&bam).unwrap());
+        writer.write_header(&header).unwrap();
+        for record in &records {
+            writer.write_record(&header, record).unwrap();
+        }
+        writer.try_finish().unwrap();
+
+        let bam = bam.to_string_lossy();
+        let regions = read_alignments(&bam, false).unwrap();
+        let spans: Vec<(String, Option<&str>)> = regions
+            .iter()
+            .map(|(region, _, name)| (region.to_string(), name.as_deref()))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                (String::from("chr1:10-14"), Some("r1")),
+                (String::from("chr1:20-29"), Some("r2")),
+                (String::from("chr1:100-119"), Some("r4")),
+                (String::from("chr1:1-80000"), Some("long")),
+            ]
+        );
+
+        let clipped: Vec<String> = read_alignments(&bam, true)
+            .unwrap()
+            .iter()
+            .map(|(region, _, _)| region.to_string())
+            .collect();
+        assert_eq!(clipped[1], "chr1:18-32");
+
+        let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+        sequences.extract(ExtractOptions::default()).unwrap();
+        let sequences: Vec<String> = sequences
+            .records()
+            .map(|record| String::from_utf8(record.sequence().as_ref().to_vec()).unwrap())
+            .collect();
+        assert_eq!(
+            sequences,
+            [
+                &reference[9..14],
+                &reference[19..29],
+                &reference[99..119],
+                &reference[..],
+            ]
+        );
+    }
+
+    #[test]
+    fn sam_alignments_span_their_reference() {
+        let dir = tempfile::tempdir().unwrap();
+        let sam = dir.path().join("aln.sam");
+        fs::write(
+            &sam,
+            "@HD\tVN:1.6\n@SQ\tSN:chr1\tLN:1000\n\
+             r1\t0\tchr1\t10\t60\t5M\t*\t0\t0\t*\t*\n\
+             r2\t4\t*\t0\t60\t*\t*\t0\t0\t*\t*\n\
+             r3\t0\tchr1\t1\t60\t3S5M\t*\t0\t0\t*\t*\n",
+        )
+        .unwrap();
+        let regions: Vec<String> = read_alignments(&sam.to_string_lossy(), true)
+            .unwrap()
+            .iter()
+            .map(|(region, _, _)| region.to_string())
+            .collect();
+        assert_eq!(regions, ["chr1:10-14", "chr1:1-5"]);
+    }
+}
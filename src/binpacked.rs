@@ -0,0 +1,59 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+// The binpacked format is a stream of records, each its length as a
+// little-endian u32 followed by that many bytes of sequence, with no names,
+// descriptions, or line breaks, so a consumer (e.g., a data loader reading
+// from a pipe) can read records without parsing FASTA. A record's length
+// prefix is written before its bases, so records longer than u32::MAX bases
+// can't be written.
+
+// Write the length prefix of a record of the given length, whose bases are
+// written straight after it.
+pub fn write_length<W: Write + ?Sized>(writer: &mut W, length: usize) -> io::Result<()> {
+    let length = u32::try_from(length).map_err(|_| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("a record of {length} bases is too long for binpacked output"),
+        )
+    })?;
+    writer.write_all(&length.to_le_bytes())
+}
+
+// Write a record: its length, then its bases.
+pub fn write_record<W: Write + ?Sized>(writer: &mut W, sequence: &[u8]) -> io::Result<()> {
+    write_length(writer, sequence.len())?;
+    writer.write_all(sequence)
+}
+
+// Read the next record, or None at the end of the stream. A stream that ends
+// partway through a record is an error. This is the reference decoder for
+// the format.
+pub fn read_record<R: Read + ?Sized>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    let mut filled = 0;
+    while filled < length.len() {
+        match reader.read(&mut length[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "binpacked stream ends in a record's length",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let mut sequence = vec![0; u32::from_le_bytes(length) as usize];
+    reader
+        .read_exact(&mut sequence)
+        .map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "binpacked stream ends in a record's sequence",
+            ),
+            _ => e,
+        })?;
+    Ok(Some(sequence))
+}
@@ -0,0 +1,53 @@
+use std::fmt::Write;
+
+use md5::{Digest, Md5};
+use sha2::Sha256;
+
+// A digest of extracted sequences.
+#[derive(Clone, Copy)]
+pub enum Checksum {
+    // MD5, as in the M5 tag of SAM/CRAM @SQ headers
+    Md5,
+    // SHA-256
+    Sha256,
+}
+
+impl Checksum {
+    // The name of the digest, used as its key in record descriptions.
+    pub fn name(self) -> &'static str {
+        match self {
+            Checksum::Md5 => "md5",
+            Checksum::Sha256 => "sha256",
+        }
+    }
+
+    // Return the hex digest of a sequence given in pieces (e.g., the records
+    // and gaps of a merged contig). Like the M5 tag of SAM headers, the
+    // digest is of the uppercased sequence, ignoring whitespace and alignment
+    // gaps ('-' and '.'), so it doesn't depend on case or soft-masking.
+    pub fn digest<'a>(self, pieces: impl IntoIterator<Item = &'a [u8]>) -> String {
+        match self {
+            Checksum::Md5 => digest_with::<Md5>(pieces),
+            Checksum::Sha256 => digest_with::<Sha256>(pieces),
+        }
+    }
+}
+
+fn digest_with<'a, D: Digest>(pieces: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut digest = D::new();
+    for piece in pieces {
+        let normalized: Vec<u8> = piece
+            .iter()
+            .filter(|base| !base.is_ascii_whitespace() && !matches!(base, b'-' | b'.'))
+            .map(u8::to_ascii_uppercase)
+            .collect();
+        digest.update(&normalized);
+    }
+    digest
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
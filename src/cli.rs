@@ -1,18 +1,627 @@
-use clap::Parser;
+use std::collections::HashMap;
 
+use clap::{builder::PossibleValue, ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use regex::Regex;
+
+use extract::{
+    Alphabet, Case, Checksum, ContigNaming, Coordinates, ExtractOptions, Filter, HeaderTemplate,
+    OutputFormat, OutputOptions, RegionFormat, SortOrder,
+};
+
+// A value of one of the library's enums given on the command line. The
+// library doesn't depend on clap, so each enum's values (and their help)
+// are listed here.
+#[derive(Clone, Copy)]
+pub struct Choice<T>(pub T);
+
+impl ValueEnum for Choice<RegionFormat> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Choice(RegionFormat::Samtools), Choice(RegionFormat::Bed)]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            RegionFormat::Samtools => {
+                PossibleValue::new("samtools").help("SAMtools region strings (chr1:1-1000, chr1)")
+            }
+            RegionFormat::Bed => PossibleValue::new("bed").help(
+                "tab-delimited BED (chrom, 0-based start, exclusive end, name, score, strand), or \
+                 BED12, whose blocks are spliced",
+            ),
+        })
+    }
+}
+
+impl ValueEnum for Choice<Coordinates> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Choice(Coordinates::ZeroBased),
+            Choice(Coordinates::OneBased),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            Coordinates::ZeroBased => PossibleValue::new("0based")
+                .help("0-based, half-open (chr1:0-100 is the first 100 bases), as in BED files"),
+            Coordinates::OneBased => PossibleValue::new("1based")
+                .help("1-based, inclusive (chr1:1-100 is the first 100 bases), as in SAMtools"),
+        })
+    }
+}
+
+impl ValueEnum for Choice<Alphabet> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Choice(Alphabet::Dna),
+            Choice(Alphabet::Rna),
+            Choice(Alphabet::Protein),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            Alphabet::Dna => PossibleValue::new("dna").help("DNA"),
+            Alphabet::Rna => {
+                PossibleValue::new("rna").help("RNA, complemented with U instead of T")
+            }
+            Alphabet::Protein => PossibleValue::new("protein").help(
+                "amino acids, which can't be complemented or translated, and are masked and \
+                 padded with X",
+            ),
+        })
+    }
+}
+
+impl ValueEnum for Choice<OutputFormat> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Choice(OutputFormat::Fasta),
+            Choice(OutputFormat::Fastq),
+            Choice(OutputFormat::TwoBit),
+            Choice(OutputFormat::Json),
+            Choice(OutputFormat::BinPacked),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            OutputFormat::Fasta => PossibleValue::new("fasta").help("FASTA"),
+            OutputFormat::Fastq => PossibleValue::new("fastq")
+                .help("FASTQ with a fixed quality character for every base"),
+            OutputFormat::TwoBit => PossibleValue::new("2bit").help(
+                "UCSC 2bit, with lowercase bases soft-masked (only A, C, G, T, and N can be \
+                 written)",
+            ),
+            OutputFormat::Json => PossibleValue::new("json").help(
+                "JSON: an array of objects with each record's name, region, strand, sequence, \
+                 and length (a single object for a merged contig)",
+            ),
+            OutputFormat::BinPacked => PossibleValue::new("binpacked").help(
+                "each record's length as a little-endian u32, then its bases, with no names or \
+                 line breaks",
+            ),
+        })
+    }
+}
+
+impl ValueEnum for Choice<SortOrder> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Choice(SortOrder::Name),
+            Choice(SortOrder::Coord),
+            Choice(SortOrder::Length),
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            SortOrder::Name => PossibleValue::new("name").help("by record name"),
+            SortOrder::Coord => PossibleValue::new("coord")
+                .help("by contig (in the order of the FASTA index), then start and end"),
+            SortOrder::Length => PossibleValue::new("length").help("by length, longest first"),
+        })
+    }
+}
+
+impl ValueEnum for Choice<Checksum> {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Choice(Checksum::Md5), Choice(Checksum::Sha256)]
+    }
+
+    fn to_possible_value(&self) -> Option<PossibleValue> {
+        Some(match self.0 {
+            Checksum::Md5 => {
+                PossibleValue::new("md5").help("MD5, as in the M5 tag of SAM/CRAM @SQ headers")
+            }
+            Checksum::Sha256 => PossibleValue::new("sha256").help("SHA-256"),
+        })
+    }
+}
+
+// Regions are extracted when no subcommand is given, so `extract ref.fa
+// regions.txt` and `extract extract ref.fa regions.txt` are the same.
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct Cli {
-    /// a FASTA-formatted file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    extract: Option<ExtractArgs>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// extract regions from a FASTA file (the default)
+    Extract(Box<ExtractArgs>),
+
+    /// build a .fai index (and .gzi, if bgzipped) for a FASTA file, replacing any existing index
+    Index {
+        /// a FASTA-formatted file, optionally bgzipped (.gz/.bgz)
+        #[arg(value_name = "FILE")]
+        fasta: String,
+    },
+
+    /// print the name and length of each contig in a FASTA file, building its index if needed
+    Stats {
+        /// a FASTA-formatted file, optionally bgzipped (.gz/.bgz)
+        #[arg(value_name = "FILE")]
+        fasta: String,
+    },
+
+    /// check regions against a FASTA file's index without extracting them, printing each region
+    /// with its status (ok, missing contig, out of bounds, starts after end, or no matching contigs
+    /// for patterns) and exiting with an error if any region has a problem
+    Check {
+        /// a FASTA-formatted file, optionally bgzipped (.gz/.bgz)
+        #[arg(value_name = "FILE")]
+        fasta: String,
+
+        /// a list of regions to check, in the same formats as for extract; use - to read regions
+        /// from stdin
+        #[arg(value_name = "FILE", required = true, num_args = 1..)]
+        regions: Vec<String>,
+
+        /// format of the regions file (default is bed for files ending in .bed, otherwise
+        /// samtools)
+        #[arg(long, value_name = "FORMAT")]
+        region_format: Option<Choice<RegionFormat>>,
+
+        /// how the coordinates of SAMtools-format regions are numbered
+        #[arg(long, value_name = "COORDS", default_value = "1based")]
+        coords: Choice<Coordinates>,
+
+        /// treat contigs as circular, so regions that start after they end are valid
+        #[arg(long)]
+        circular: bool,
+    },
+
+    /// serve region queries for a FASTA file over a Unix socket, keeping it open between queries
+    #[cfg(feature = "serve")]
+    Serve {
+        /// a FASTA-formatted file, optionally bgzipped (.gz/.bgz)
+        #[arg(value_name = "FILE")]
+        fasta: String,
+
+        /// path of the Unix socket to listen on
+        #[arg(value_name = "SOCKET")]
+        socket: String,
+    },
+
+    /// send regions (one per line, in SAMtools region format) to a server started with serve and
+    /// print the FASTA it answers with
+    #[cfg(feature = "serve")]
+    Query {
+        /// path of the server's Unix socket
+        #[arg(value_name = "SOCKET")]
+        socket: String,
+
+        /// a list of regions to extract; use - to read regions from stdin
+        #[arg(value_name = "FILE", default_value = "-")]
+        regions: String,
+    },
+
+    /// print a completion script for a shell to stdout (e.g., extract completions bash >
+    /// ~/.local/share/bash-completion/completions/extract)
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: Shell,
+    },
+}
+
+impl Cli {
+    pub fn into_command(self) -> Command {
+        match (self.command, self.extract) {
+            (Some(command), _) => command,
+            (None, Some(args)) => Command::Extract(Box::new(args)),
+            (None, None) => unreachable!("clap requires the extract arguments"),
+        }
+    }
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("merging").args(["merge_contigs", "group_by"])))]
+pub struct ExtractArgs {
+    /// a FASTA-formatted file, optionally bgzipped (.gz/.bgz); use - to read from stdin
+    /// (the whole reference is then held in memory so it can be indexed and queried), or, when
+    /// built with the remote feature, an HTTP(S) URL of an uncompressed FASTA file with its .fai
+    /// index next to it, which is read with range requests
     #[arg(value_name = "FILE", required = true)]
     fasta: String,
 
-    /// a list of regions to extract in SAMtools region format (chr1:1-1000, chr1);
-    /// a negative sign in front of a region causes the extracted region to be reverse complemented
-    #[arg(value_name = "FILE", required = true)]
-    regions: String,
+    /// also read contigs from this FASTA file (e.g., alt or decoy contigs kept apart from the main
+    /// assembly), indexed like the main one, so regions can be on contigs in any of the files; a
+    /// contig name in more than one file is an error; can be given more than once
+    #[arg(long, value_name = "FILE", required = false)]
+    extra_fasta: Vec<String>,
+
+    /// read the FASTA file from the start without building or using a .fai index, keeping only the
+    /// contigs of the regions in memory and stopping once all of them have been read; this is
+    /// faster than indexing for a one-off extraction from contigs near the start of a large
+    /// FASTA, and slower for contigs near its end (regions are always extracted on one thread)
+    #[arg(long, conflicts_with = "extra_fasta", required = false)]
+    no_index: bool,
+
+    /// read the FASTA index from this .fai file instead of the one next to the FASTA file (e.g.,
+    /// for a reference in a read-only directory); it's built and written here if it doesn't exist,
+    /// unless --fai-out is given
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "no_index",
+        required = false
+    )]
+    fai: Option<String>,
+
+    /// write a newly built FASTA index to this file instead of next to the FASTA file (or to
+    /// --fai), e.g., to a temporary directory
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "no_index",
+        required = false
+    )]
+    fai_out: Option<String>,
+
+    /// a list of regions to extract in SAMtools region format (chr1:1-1000, chr1; commas in
+    /// coordinates, as in chr1:1,000-2,000, are ignored; coordinates can also be percentages of the
+    /// contig's length, as in chr1:10%-90% or chr1:1000-90%, or the first or last N bases of the
+    /// contig, as in chr1:first=1000 or chr1:last=1000), optionally with a strand, as
+    /// [-]name[:start[-end]][:+|:-], or followed by whitespace and a strand (+, -, fwd, or rev);
+    /// a negative sign in front of a region, a :- suffix, or a - or rev strand causes the extracted
+    /// region to be reverse complemented (the strand takes precedence over the suffix, and the
+    /// suffix over the sign);
+    /// a region can be named in the output by preceding it with a name and =, as in
+    /// promoter=-chr1:100-200 (splitting on the first =);
+    /// the contig can also be a pattern that expands to every contig in the index it matches: a
+    /// glob matching the whole name (chr*, scaffold_?, chr[12]) or a regular expression between
+    /// slashes matching anywhere in it (/scaffold_\d+/, /^chr\d+$/), though a contig named like
+    /// the region or pattern (HLA-A*01:01:01:01) is taken literally, or the MD5 digest of its
+    /// sequence (md5:<digest>, as in CRAM reference registries), found by digesting contigs as
+    /// needed or with --contig-md5;
+    /// use - to read regions from stdin; regions from multiple files are extracted in the order given;
+    /// gzipped regions files are decompressed
+    #[arg(value_name = "FILE", required_unless_present_any = ["from_gff", "from_vcf", "from_bam", "pattern"], num_args = 1..)]
+    regions: Vec<String>,
+
+    /// also extract features from this GFF3 file (or GTF, for files ending in .gtf), after any
+    /// regions files; features on the - strand are reverse complemented
+    #[arg(long, value_name = "FILE", required = false)]
+    from_gff: Option<String>,
+
+    /// also extract the reference bases of each variant in this VCF file (optionally bgzipped),
+    /// after any regions files and features, named by the variant's ID; use --flank to extract
+    /// the sequence around each variant (e.g., for probe or primer design)
+    #[arg(long, value_name = "FILE", required = false)]
+    from_vcf: Option<String>,
+
+    /// also extract the reference each mapped alignment in this SAM or BAM file spans (from its
+    /// position and the reference length of its CIGAR), after any regions files, features, and
+    /// variants, named by the read's name; needs the bam feature
+    #[arg(long, value_name = "FILE", required = false)]
+    from_bam: Option<String>,
+
+    /// with --from-bam, extend each alignment's region by its soft-clipped bases on either side,
+    /// to include the reference the clipped bases would have covered
+    #[arg(long, requires = "from_bam", required = false)]
+    soft_clips: bool,
+
+    /// also extract every whole contig matching this pattern (a glob or /regex/, as in regions
+    /// files), after any regions files, features, variants, and alignments; can be given more
+    /// than once
+    #[arg(long, value_name = "PATTERN", required = false)]
+    pattern: Vec<String>,
+
+    /// type of feature to extract from the GFF3/GTF file
+    #[arg(
+        long,
+        value_name = "TYPE",
+        requires = "from_gff",
+        default_value = "exon",
+        required = false
+    )]
+    feature_type: String,
+
+    /// format of the regions file (default is bed for files ending in .bed, otherwise samtools);
+    /// BED regions take their strand from column 6 and their output name from column 4, and
+    /// BED12 regions with several blocks (e.g., exons) are extracted as their blocks spliced
+    /// together, as by bedtools getfasta -split
+    #[arg(long, value_name = "FORMAT", required = false)]
+    region_format: Option<Choice<RegionFormat>>,
+
+    /// how the coordinates of SAMtools-format regions (in regions and mask files) are numbered;
+    /// 0-based regions are half-open, so chr1:0-100 (0based) and chr1:1-100 (1based) are the
+    /// same first 100 bases; BED regions are always 0-based
+    #[arg(
+        long,
+        value_name = "COORDS",
+        default_value = "1based",
+        required = false
+    )]
+    coords: Choice<Coordinates>,
+
+    /// resolve names in the regions files (e.g., gene symbols like BRCA1) to regions using this
+    /// TSV of name and region (e.g., BRCA1<TAB>chr17:43044295-43125364:-)
+    #[arg(long, value_name = "FILE", required = false)]
+    name_map: Option<String>,
+
+    /// rename the contigs of regions that aren't in the FASTA index to an alias that is, using
+    /// this file of names a contig goes by, one contig per line (e.g., chr1<TAB>1 or
+    /// chrM<TAB>MT); each name on a line is an alias of the others
+    #[arg(long, value_name = "FILE", required = false)]
+    chr_alias: Option<String>,
+
+    /// rename the contigs of regions that aren't in the FASTA index to UCSC names (e.g., 1 to
+    /// chr1 and MT to chrM), after any --chr-alias
+    #[arg(long, conflicts_with = "ensembl", required = false)]
+    ucsc: bool,
+
+    /// rename the contigs of regions that aren't in the FASTA index to Ensembl names (e.g., chr1
+    /// to 1 and chrM to MT), after any --chr-alias
+    #[arg(long, required = false)]
+    ensembl: bool,
+
+    /// resolve contigs given by MD5 digest in regions (e.g., md5:<digest>:1-100, as in CRAM
+    /// reference registries) with this file of contig names and their digests (e.g., chr1<TAB>
+    /// <M5 of chr1>), instead of digesting the FASTA's contigs as needed
+    #[arg(long, value_name = "FILE", required = false)]
+    contig_md5: Option<String>,
+
+    /// fail on regions that cannot be parsed instead of skipping them with a warning, and on a
+    /// FASTA index older than the FASTA file instead of rebuilding it
+    #[arg(long, required = false)]
+    strict: bool,
+
+    /// print each parsed region (name, start, end, length, strand) and exit without extracting;
+    /// no FASTA index is needed
+    #[arg(long, required = false)]
+    list_regions: bool,
+
+    /// print the number of bases that would be extracted from each contig (contig and bases, as
+    /// a TSV) and exit without writing sequences; lengths come from the FASTA index alone unless
+    /// translating, padding, applying variants, or filtering, when only records that pass the
+    /// --filter are counted
+    #[arg(long, conflicts_with = "list_regions", required = false)]
+    count: bool,
+
+    /// alphabet of the FASTA file; protein regions can't be reverse complemented or translated
+    #[arg(long, value_name = "ALPHABET", default_value = "dna", required = false)]
+    alphabet: Choice<Alphabet>,
+
+    /// convert extracted sequences to RNA, replacing T with U (after any reverse complementing)
+    #[arg(long, conflicts_with = "translate", required = false)]
+    rna: bool,
+
+    /// reverse complement every region; this overrides per-region strand, so regions with a
+    /// negative sign (or BED strand -) are reverse complemented once, like all others
+    #[arg(long, required = false)]
+    revcomp_all: bool,
+
+    /// extract every region from the forward strand, ignoring per-region strands (a negative sign,
+    /// :- suffix, or - or rev strand, BED and GFF strands, and name map strands); the inverse of
+    /// --revcomp-all, which it can't be given with
+    #[arg(long, conflicts_with = "revcomp_all", required = false)]
+    no_reverse_complement: bool,
+
+    /// complement bases with this table (lines of a base and its complement, e.g., M H for a
+    /// modified base) when reverse complementing, matching bases exactly, so lowercase bases are
+    /// listed separately; unlisted bases use the default DNA/IUPAC complements
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "reverse_only",
+        required = false
+    )]
+    complement_table: Option<String>,
+
+    /// reverse regions marked for reverse complementing without complementing their bases
+    /// (e.g., -chr1:1-4 of ACGG gives GGCA rather than CCGT)
+    #[arg(long, required = false)]
+    reverse_only: bool,
+
+    /// replace bases overlapping the regions in this file (SAMtools or BED format, as for regions
+    /// files) with N; mask regions are in genomic coordinates and their strand is ignored
+    #[arg(long, value_name = "FILE", required = false)]
+    mask: Option<String>,
+
+    /// remove the spans of the regions in this file (SAMtools or BED format) from the regions to
+    /// extract, splitting regions around them (pieces of a reverse complemented region are in
+    /// reverse order); excluded regions are in genomic coordinates and their strand is ignored
+    #[arg(long, value_name = "FILE", required = false)]
+    exclude: Option<String>,
+
+    /// apply the variants in this VCF file (optionally bgzipped) to the extracted bases, like
+    /// bcftools consensus, before any reverse complementing; only the first alternate allele of
+    /// each variant is used, and variants overlapping an earlier variant or the end of a region
+    /// are skipped with a warning
+    #[arg(long, value_name = "FILE", required = false)]
+    consensus: Option<String>,
+
+    /// treat contigs as circular, so a region that starts after it ends (e.g., mito:16000-200)
+    /// wraps around the end of the contig
+    #[arg(long, required = false)]
+    circular: bool,
+
+    /// merge regions on the same contig and strand that overlap or are adjacent (as bedtools merge
+    /// does) before extracting them; regions are then sorted by contig and start instead of kept
+    /// in the order given, and merged regions are named after the merged region
+    #[arg(long, required = false)]
+    merge_overlaps: bool,
+
+    /// clamp regions that extend past the end of their contig instead of failing (with a warning)
+    #[arg(long, required = false)]
+    clamp: bool,
+
+    /// extend each region by this many bases on both sides (stopping at contig ends)
+    #[arg(long, value_name = "N", default_value_t = 0, required = false)]
+    flank: usize,
+
+    /// extend each region by this many bases before its start (added to --flank); left and right
+    /// are genomic coordinates, so for reverse complemented regions this is the 3' end
+    #[arg(long, value_name = "N", default_value_t = 0, required = false)]
+    flank_left: usize,
+
+    /// extend each region by this many bases after its end (added to --flank); left and right
+    /// are genomic coordinates, so for reverse complemented regions this is the 5' end
+    #[arg(long, value_name = "N", default_value_t = 0, required = false)]
+    flank_right: usize,
+
+    /// extract windows of this many bases tiling each region (after clamping, flanking, excluding,
+    /// and merging) instead of the whole region, each named after the region with its window
+    /// number (e.g., chr1:100-600_w1); windows of reverse complemented regions start from their
+    /// end, so windows follow the extracted sequence
+    #[arg(long, value_name = "SIZE", required = false)]
+    window: Option<usize>,
+
+    /// start a window every this many bases (default is the window size, so windows don't
+    /// overlap)
+    #[arg(long, value_name = "STEP", requires = "window", required = false)]
+    step: Option<usize>,
+
+    /// keep the last window of each region if it's shorter than the window size (running to the
+    /// end of the region), and a window of the whole region if it's shorter, instead of dropping
+    /// them
+    #[arg(long, requires = "window", required = false)]
+    keep_partial: bool,
 
-    /// output to this location (default is stdout)
+    /// fail if any region (after clamping and flanking) is longer than this many bases
+    #[arg(long, value_name = "N", required = false)]
+    max_region_length: Option<usize>,
+
+    /// skip regions (after clamping and flanking) shorter than this many bases, with a note
+    #[arg(long, value_name = "N", required = false)]
+    min_length: Option<usize>,
+
+    /// keep only the extracted records that pass this filter, e.g., 'gc > 0.4 && len > 100'; a
+    /// filter compares the variables len (or length, the number of bases), gc (the fraction of
+    /// bases that are G or C), and n_count (the number of Ns) with numbers or each other, using <,
+    /// <=, >, >=, ==, and !=, combined with && (and), || (or), ! (not), and parentheses; records
+    /// are filtered as extracted (after reverse complementing, translating, and padding)
+    #[arg(long, value_name = "EXPR", value_parser = Filter::parse, required = false)]
+    filter: Option<Filter>,
+
+    /// extract a random sample of this many regions (in the order given), e.g., to spot-check a
+    /// large regions file
+    #[arg(long, value_name = "N", required = false)]
+    sample: Option<usize>,
+
+    /// seed for --sample, so the same regions are sampled on every run
+    #[arg(long, value_name = "SEED", requires = "sample", required = false)]
+    seed: Option<u64>,
+
+    /// convert extracted sequences to uppercase
+    #[arg(long, conflicts_with = "lowercase", required = false)]
+    uppercase: bool,
+
+    /// convert extracted sequences to lowercase
+    #[arg(long, required = false)]
+    lowercase: bool,
+
+    /// replace soft-masked (lowercase) bases with N (e.g., acGTac becomes NNGTNN); bases are masked as
+    /// soft-masked in the reference, before any reverse complement or case conversion, so a
+    /// --complement-table that changes case doesn't change which bases are masked
+    #[arg(long, required = false)]
+    hard_mask: bool,
+
+    /// translate extracted sequences to amino acids using the standard genetic code
+    /// (after reverse complementing; stop codons are written as *)
+    #[arg(long, required = false)]
+    translate: bool,
+
+    /// reading frame offset for translation
+    #[arg(
+        long,
+        requires = "translate",
+        default_value_t = 0,
+        value_parser = clap::value_parser!(u8).range(0..=2),
+        required = false
+    )]
+    frame: u8,
+
+    /// pad each extracted sequence at its end with N (X, when translating) to this length, or
+    /// truncate it to this length with a warning, e.g., for fixed-width model inputs
+    #[arg(long, value_name = "N", conflicts_with = "merging", required = false)]
+    pad_to_length: Option<usize>,
+
+    /// name each record after its region and strand (e.g., chr1:1000-2000(-)) instead of the
+    /// region string or BED/GFF name
+    #[arg(long, required = false)]
+    output_names_from_regions: bool,
+
+    /// name each record with this format, to tell apart records from the same contig or with the
+    /// same name (e.g., "{name}_{start}_{end}" or "{name}:{start}-{end}" for tools that can't
+    /// read names with colons), with {name} replaced by the region's name (or its contig, if it
+    /// has none), {contig}, {start}, and {end} by its contig and 1-based start and end, {strand}
+    /// by + or -, {region} by the region string, and {length} by the number of bases
+    #[arg(
+        long,
+        value_name = "FMT",
+        value_parser = HeaderTemplate::parse_name,
+        conflicts_with = "output_names_from_regions",
+        required = false
+    )]
+    name_suffix: Option<HeaderTemplate>,
+
+    /// set each record's description to its region and strand (e.g., chr1:1000-2000 strand=-)
+    #[arg(long, required = false)]
+    annotate: bool,
+
+    /// keep the description of a whole contig's definition line (everything after its name) in
+    /// its record, after any --annotate description; subregions of contigs have no description
+    #[arg(long, required = false)]
+    keep_description: bool,
+
+    /// print a summary of the extraction (regions, bases, GC content) to stderr
+    #[arg(long, required = false)]
+    stats: bool,
+
+    /// show a progress bar of regions extracted out of the total on stderr (when it's a terminal)
+    #[arg(long, required = false)]
+    progress: bool,
+
+    /// number of threads used to extract regions, and to index a plaintext FASTA file that has no
+    /// index
+    #[arg(long, value_name = "N", default_value_t = 1, required = false)]
+    threads: usize,
+
+    /// write each record as soon as it's extracted, with extraction and writing on separate
+    /// threads, instead of extracting every region before writing; this overlaps reading the FASTA
+    /// with writing the output and holds only a bounded number of records in memory, but only
+    /// writes records in the order of the regions to a single FASTA or FASTQ output
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "merging", "split_dir", "order", "provenance", "merge_bed", "merge_gff", "checksum_file", "stats",
+            "count", "list_regions", "threads", "split_strand", "sort_output",
+        ],
+        required = false
+    )]
+    pipeline: bool,
+
+    /// output to this location (default is stdout); output ending in .gz or .bgz is BGZF-compressed
     #[arg(short, long, value_name = "FILE", required = false)]
     output: Option<String>,
 
@@ -20,31 +629,338 @@ pub struct Cli {
     #[arg(short, long, required = false)]
     merge_contigs: bool,
 
-    /// name of the single merged contig (default is regions filename without extension)
+    /// merge contigs in groups instead, one per key: the first capture group (or whole match) of
+    /// this regular expression in each record name (e.g., '^([^.]+)' groups gene1.exon1 and
+    /// gene1.exon2 as gene1); records whose names don't match are grouped by their whole name
+    #[arg(long, value_name = "REGEX", value_parser = Regex::new, required = false)]
+    group_by: Option<Regex>,
+
+    /// write records (and merge them) in the order their names are listed in this file, one per
+    /// line, instead of the order of the regions; records not listed are written last
+    #[arg(long, value_name = "FILE", required = false)]
+    order: Option<String>,
+
+    /// write records (and merge them) sorted by name, coordinate, or length (longest first),
+    /// instead of the order of the regions
+    #[arg(long, value_name = "ORDER", conflicts_with = "order", required = false)]
+    sort_output: Option<Choice<SortOrder>>,
+
+    /// name of the single merged contig (default is first regions filename without extension)
     #[arg(short, requires = "merge_contigs", required = false)]
     contig_name: Option<String>,
 
     /// insert gaps of this length between sequences
+    #[arg(short, requires = "merging", default_value_t = 0, required = false)]
+    gap_size: usize,
+
+    /// character used to fill gaps between sequences
     #[arg(
-        short,
-        requires = "merge_contigs",
-        default_value_t = 0,
+        long,
+        value_name = "CHAR",
+        requires = "merging",
+        default_value_t = 'N',
+        value_parser = parse_ascii_char,
         required = false
     )]
-    gap_size: usize,
-}
+    gap_char: char,
 
-impl Cli {
-    pub fn get_input(&self) -> (String, String) {
-        (self.fasta.clone(), self.regions.clone())
-    }
+    /// insert this literal sequence between sequences (e.g., a linker or restriction site),
+    /// instead of gaps of -g and --gap-char
+    #[arg(long, value_name = "SEQ", requires = "merging", required = false)]
+    gap_seq: Option<String>,
+
+    /// trim runs of N from the start and end of each extracted sequence (after any reverse
+    /// complementing), e.g., for regions running into assembly gaps; sequences of only Ns are
+    /// left empty, and --stats reports how many Ns were trimmed
+    #[arg(long, required = false)]
+    trim_n: bool,
+
+    /// warn about each extracted sequence with runs of at least this many Ns, which likely span
+    /// assembly gaps, listing the runs' positions in the sequence (before any --trim-n); --stats
+    /// reports how many regions span gaps
+    #[arg(long, value_name = "MIN_N", required = false)]
+    warn_gaps: Option<usize>,
+
+    /// build each record's definition line from this template, with {name}, {region}, {strand},
+    /// and {length} replaced by the record's name, region, strand (+ or -), and length (e.g.,
+    /// "{name}|{region}|{strand}"); text after the first space becomes the description, {{ and }}
+    /// are literal braces, and merged contigs keep their own names
+    #[arg(long, value_name = "TEMPLATE", value_parser = HeaderTemplate::parse, required = false)]
+    header_template: Option<HeaderTemplate>,
+
+    /// output format
+    #[arg(long, value_name = "FORMAT", default_value = "fasta", required = false)]
+    format: Choice<OutputFormat>,
+
+    /// quality character used for every base of FASTQ output (default is I, Phred 40)
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value_t = 'I',
+        value_parser = parse_ascii_char,
+        required = false
+    )]
+    quality_char: char,
+
+    /// number of bases per line of FASTA output (0 writes each sequence on a single line)
+    #[arg(long, value_name = "N", default_value_t = 80, required = false)]
+    line_width: usize,
+
+    /// write each region/contig to its own file in this directory, named after the record
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["merge_contigs", "output"], required = false)]
+    split_dir: Option<String>,
+
+    /// write forward and reverse complemented records to two files named after the output, with
+    /// .fwd or .rev before its extension (e.g., out.fwd.fa and out.rev.fa for -o out.fa)
+    #[arg(
+        long,
+        requires = "output",
+        conflicts_with_all = ["split_dir", "provenance", "merge_bed", "merge_gff", "checksum_file"],
+        required = false
+    )]
+    split_strand: bool,
+
+    /// size in bytes of the buffer output is written through (before any compression)
+    #[arg(long, value_name = "BYTES", default_value_t = 1 << 16, required = false)]
+    buffer_size: usize,
 
-    pub fn get_output(&self) -> (Option<String>, bool, Option<String>, usize) {
+    /// compress output with BGZF (implied when the output file ends in .gz or .bgz); BGZF files
+    /// are valid gzip and can be indexed and queried by region, e.g., by samtools faidx
+    #[arg(long, required = false)]
+    bgzip: bool,
+
+    /// compress output with plain gzip instead of BGZF, also for output files ending in .gz, for
+    /// tools that only decompress it whole (e.g., zcat); plain gzip output can't be indexed
+    #[arg(long, conflicts_with_all = ["bgzip", "index_output"], required = false)]
+    plain_gzip: bool,
+
+    /// append to the output file (or split and provenance files) instead of replacing it, e.g., to
+    /// collect the regions of several runs in one FASTA; not for 2bit or JSON output
+    #[arg(long, required = false)]
+    append: bool,
+
+    /// write a .fai index (and .gzi, if compressed) for each FASTA output file
+    #[arg(long, required = false)]
+    index_output: bool,
+
+    /// write a TSV with a line per extracted record: output record name, source contig, start,
+    /// end, strand, and offset of the record within the output record (nonzero when merging)
+    #[arg(long, value_name = "FILE", required = false)]
+    provenance: Option<String>,
+
+    /// write a BED file of where each record is in its merged contig: the merged contig's name,
+    /// the record's 0-based start and end in it (accounting for gaps), name, score 0, and strand
+    #[arg(long, value_name = "FILE", requires = "merging", required = false)]
+    merge_bed: Option<String>,
+
+    /// write a GFF3 file of where each record is in its merged contig, for reading features of the
+    /// merged sequence: a region feature per record with its 1-based start and end in the merged
+    /// contig, strand, name, and source region
+    #[arg(long, value_name = "FILE", requires = "merging", required = false)]
+    merge_gff: Option<String>,
+
+    /// write bases other than A, C, G, T, and N (e.g., IUPAC ambiguity codes like R) as N in 2bit
+    /// output, and allow a --gap-seq that isn't nucleotides, instead of failing
+    #[arg(long, required = false)]
+    force: bool,
+
+    /// write a checksum of each output sequence in its description, e.g., md5=...; as in the M5 tag
+    /// of SAM/CRAM headers, the sequence is uppercased and alignment gap characters (- and .) are
+    /// ignored, but the N (or --gap-char) bases between the records of a merged contig are included
+    #[arg(long, value_name = "ALGORITHM", required = false)]
+    checksum: Option<Choice<Checksum>>,
+
+    /// write the checksums to this TSV (record name and checksum) instead of the descriptions
+    #[arg(long, value_name = "FILE", requires = "checksum", required = false)]
+    checksum_file: Option<String>,
+}
+
+impl ExtractArgs {
+    pub fn get_input(&self) -> (String, Vec<String>, Option<RegionFormat>, Coordinates, bool) {
         (
-            self.output.clone(),
-            self.merge_contigs,
-            self.contig_name.clone(),
-            self.gap_size,
+            self.fasta.clone(),
+            self.regions.clone(),
+            self.region_format.map(|format| format.0),
+            self.coords.0,
+            self.strict,
         )
     }
+
+    pub fn get_features(&self) -> Option<(String, String)> {
+        self.from_gff
+            .clone()
+            .map(|file| (file, self.feature_type.clone()))
+    }
+
+    pub fn get_vcf(&self) -> Option<String> {
+        self.from_vcf.clone()
+    }
+
+    pub fn get_bam(&self) -> Option<(String, bool)> {
+        self.from_bam.clone().map(|file| (file, self.soft_clips))
+    }
+
+    pub fn get_patterns(&self) -> Vec<String> {
+        self.pattern.clone()
+    }
+
+    pub fn get_sample(&self) -> Option<(usize, Option<u64>)> {
+        self.sample.map(|size| (size, self.seed))
+    }
+
+    pub fn get_order(&self) -> Option<String> {
+        self.order.clone()
+    }
+
+    pub fn get_complement_table(&self) -> Option<String> {
+        self.complement_table.clone()
+    }
+
+    pub fn get_name_map(&self) -> Option<String> {
+        self.name_map.clone()
+    }
+
+    pub fn get_chr_alias(&self) -> Option<String> {
+        self.chr_alias.clone()
+    }
+
+    pub fn get_contig_md5(&self) -> Option<String> {
+        self.contig_md5.clone()
+    }
+
+    pub fn get_consensus(&self) -> Option<String> {
+        self.consensus.clone()
+    }
+
+    pub fn get_exclude(&self) -> Option<String> {
+        self.exclude.clone()
+    }
+
+    pub fn get_mask(&self) -> Option<String> {
+        self.mask.clone()
+    }
+
+    pub fn stats(&self) -> bool {
+        self.stats
+    }
+
+    pub fn list_regions(&self) -> bool {
+        self.list_regions
+    }
+
+    pub fn count(&self) -> bool {
+        self.count
+    }
+
+    pub fn pipeline(&self) -> bool {
+        self.pipeline
+    }
+
+    pub fn progress(&self) -> bool {
+        self.progress
+    }
+
+    pub fn get_extract(&self) -> ExtractOptions {
+        ExtractOptions {
+            case: if self.uppercase {
+                Some(Case::Upper)
+            } else if self.lowercase {
+                Some(Case::Lower)
+            } else {
+                None
+            },
+            translate: self.translate,
+            frame: self.frame as usize,
+            annotate: self.annotate,
+            threads: self.threads,
+            revcomp_all: self.revcomp_all,
+            no_revcomp: self.no_reverse_complement,
+            clamp: self.clamp,
+            flank_left: self.flank + self.flank_left,
+            flank_right: self.flank + self.flank_right,
+            max_region_length: self.max_region_length,
+            reverse_only: self.reverse_only,
+            mask: Vec::new(),
+            exclude: Vec::new(),
+            region_names: self.output_names_from_regions,
+            min_length: self.min_length,
+            circular: self.circular,
+            name_map: HashMap::new(),
+            hard_mask: self.hard_mask,
+            merge_overlaps: self.merge_overlaps,
+            consensus: HashMap::new(),
+            no_index: self.no_index,
+            progress: None,
+            pad_to_length: self.pad_to_length,
+            sample: self.sample,
+            seed: self.seed,
+            alphabet: self.alphabet.0,
+            rna: self.rna,
+            strict: self.strict,
+            complement_table: HashMap::new(),
+            window: self.window,
+            step: self.step,
+            keep_partial: self.keep_partial,
+            fai: self.fai.clone(),
+            fai_out: self.fai_out.clone(),
+            extra_fasta: self.extra_fasta.clone(),
+            filter: self.filter.clone(),
+            chr_alias: HashMap::new(),
+            contig_md5: HashMap::new(),
+            keep_description: self.keep_description,
+            name_format: self.name_suffix.clone(),
+            contig_naming: if self.ucsc {
+                Some(ContigNaming::Ucsc)
+            } else if self.ensembl {
+                Some(ContigNaming::Ensembl)
+            } else {
+                None
+            },
+        }
+    }
+
+    pub fn get_output(&self) -> OutputOptions {
+        OutputOptions {
+            output_location: self.output.clone(),
+            merge: self.merge_contigs,
+            contig_name: self.contig_name.clone(),
+            gap_size: self.gap_size,
+            gap_char: self.gap_char as u8,
+            gap_seq: self
+                .gap_seq
+                .as_ref()
+                .map(|gap_seq| gap_seq.as_bytes().to_vec()),
+            format: self.format.0,
+            quality: self.quality_char as u8,
+            line_width: self.line_width,
+            split_dir: self.split_dir.clone(),
+            bgzip: self.bgzip,
+            plain_gzip: self.plain_gzip,
+            split_strand: self.split_strand,
+            index: self.index_output,
+            group_by: self.group_by.clone(),
+            provenance: self.provenance.clone(),
+            merge_bed: self.merge_bed.clone(),
+            merge_gff: self.merge_gff.clone(),
+            force: self.force,
+            append: self.append,
+            checksum: self.checksum.map(|checksum| checksum.0),
+            checksum_file: self.checksum_file.clone(),
+            order: HashMap::new(),
+            sort: self.sort_output.map(|sort| sort.0),
+            trim_n: self.trim_n,
+            warn_gaps: self.warn_gaps,
+            header_template: self.header_template.clone(),
+            buffer_size: self.buffer_size,
+        }
+    }
+}
+
+// Quality and gap characters must be a single printable ASCII character
+// ('!' through '~').
+fn parse_ascii_char(s: &str) -> Result<char, String> {
+    match s.parse::<char>() {
+        Ok(c) if ('!'..='~').contains(&c) => Ok(c),
+        _ => Err(String::from("expected a single printable ASCII character")),
+    }
 }
@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+
+use crate::Diagnostic;
+
+// A variant on a contig: its 1-based position and its reference and
+// alternate bases, as in a VCF record (e.g., 100 ACG A for a deletion).
+pub struct Variant {
+    pub position: usize,
+    pub reference: Vec<u8>,
+    pub alternate: Vec<u8>,
+}
+
+// Apply variants to bases extracted from a contig, starting at a 1-based
+// position, like `bcftools consensus`. Variants must be sorted by position.
+// Each variant replaces its reference bases with its alternate bases, so
+// indels change the length of the sequence; positions after an indel are
+// found by tracking how much of the extracted bases has been consumed, not
+// by offsetting into the output. Variants whose reference bases extend past
+// the extracted bases or overlap an earlier variant are skipped with a
+// warning, and variants over N bases (e.g., masked bases) are skipped. A
+// variant whose reference bases don't match the extracted bases is an error.
+pub fn apply(
+    contig: &str,
+    start: usize,
+    bases: &[u8],
+    variants: &[Variant],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<u8>> {
+    let end = start + bases.len();
+    let first = variants.partition_point(|variant| variant.position < start);
+    let mut consensus = Vec::with_capacity(bases.len());
+    let mut consumed = 0;
+
+    for variant in variants[first..]
+        .iter()
+        .take_while(|variant| variant.position < end)
+    {
+        let offset = variant.position - start;
+        let reference_end = offset + variant.reference.len();
+        if offset < consumed {
+            diagnostics.push(Diagnostic::Warning(format!(
+                "skipping variant at {contig}:{}, which overlaps an earlier variant",
+                variant.position
+            )));
+            continue;
+        }
+        if reference_end > bases.len() {
+            diagnostics.push(Diagnostic::Warning(format!(
+                "skipping variant at {contig}:{}, which extends past the extracted region",
+                variant.position
+            )));
+            continue;
+        }
+        let reference = &bases[offset..reference_end];
+        if reference
+            .iter()
+            .any(|base| base.eq_ignore_ascii_case(&b'N'))
+        {
+            continue;
+        }
+        if !reference.eq_ignore_ascii_case(&variant.reference) {
+            bail!(
+                "variant at {contig}:{} has reference bases {} but the FASTA has {}",
+                variant.position,
+                String::from_utf8_lossy(&variant.reference),
+                String::from_utf8_lossy(reference)
+            );
+        }
+        consensus.extend_from_slice(&bases[consumed..offset]);
+        consensus.extend_from_slice(&variant.alternate);
+        consumed = reference_end;
+    }
+    consensus.extend_from_slice(&bases[consumed..]);
+    Ok(consensus)
+}
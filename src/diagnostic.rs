@@ -0,0 +1,19 @@
+use std::fmt;
+
+// Something to tell the user that doesn't stop extraction, e.g., a skipped
+// region or a rebuilt index. Diagnostics are returned to the caller rather
+// than printed, so it decides where (and whether) they go.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Diagnostic {
+    Warning(String),
+    Note(String),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::Warning(message) => write!(f, "warning: {message}"),
+            Diagnostic::Note(message) => write!(f, "note: {message}"),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+// A filter on extracted records, written as an expression over each
+// record's metrics, e.g., "gc > 0.4 && len > 100". The grammar is
+//
+//   expression := and ("||" and)*
+//   and        := term ("&&" term)*
+//   term       := "!" term | "(" expression ")" | value operator value
+//   operator   := "<" | "<=" | ">" | ">=" | "==" | "!="
+//   value      := number | variable
+//
+// where the variables are len (or length), the number of bases; gc, the
+// fraction of bases that are G or C (0 for an empty record); and n_count,
+// the number of N bases. Whitespace between tokens is ignored.
+#[derive(Clone)]
+pub struct Filter(Expression);
+
+#[derive(Clone)]
+enum Expression {
+    Or(Box<Expression>, Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Value, Operator, Value),
+}
+
+#[derive(Clone, Copy)]
+enum Value {
+    Number(f64),
+    Length,
+    Gc,
+    NCount,
+}
+
+#[derive(Clone, Copy)]
+enum Operator {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Variable(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+impl Filter {
+    // Parse a filter expression, failing on unknown variables and syntax
+    // errors, so a mistyped filter is reported before anything is extracted.
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let tokens = tokenize(expression)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let parsed = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Self(parsed)),
+            Some(_) => Err(String::from("unexpected text after the end of the filter")),
+        }
+    }
+
+    // Whether a record's sequence passes the filter.
+    pub fn matches(&self, sequence: &[u8]) -> bool {
+        let length = sequence.len();
+        let gc = sequence
+            .iter()
+            .filter(|base| matches!(base.to_ascii_uppercase(), b'G' | b'C'))
+            .count();
+        let n_count = sequence
+            .iter()
+            .filter(|base| base.eq_ignore_ascii_case(&b'N'))
+            .count();
+        let metrics = Metrics {
+            length: length as f64,
+            gc: if length > 0 {
+                gc as f64 / length as f64
+            } else {
+                0.0
+            },
+            n_count: n_count as f64,
+        };
+        metrics.evaluate(&self.0)
+    }
+}
+
+// The metrics of a record that filters compare.
+struct Metrics {
+    length: f64,
+    gc: f64,
+    n_count: f64,
+}
+
+impl Metrics {
+    fn evaluate(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Or(left, right) => self.evaluate(left) || self.evaluate(right),
+            Expression::And(left, right) => self.evaluate(left) && self.evaluate(right),
+            Expression::Not(inner) => !self.evaluate(inner),
+            Expression::Compare(left, operator, right) => {
+                let (left, right) = (self.value(*left), self.value(*right));
+                match operator {
+                    Operator::Less => left < right,
+                    Operator::LessEqual => left <= right,
+                    Operator::Greater => left > right,
+                    Operator::GreaterEqual => left >= right,
+                    Operator::Equal => left == right,
+                    Operator::NotEqual => left != right,
+                }
+            }
+        }
+    }
+
+    fn value(&self, value: Value) -> f64 {
+        match value {
+            Value::Number(number) => number,
+            Value::Length => self.length,
+            Value::Gc => self.gc,
+            Value::NCount => self.n_count,
+        }
+    }
+}
+
+// Split a filter into numbers, variables, operators, and parentheses.
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    const OPERATORS: [&str; 10] = ["&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "="];
+
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            rest = &rest[1..];
+        } else if let Some(operator) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            if *operator == "=" {
+                return Err(String::from("use == to compare for equality"));
+            }
+            tokens.push(Token::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            let number = rest[..end]
+                .parse()
+                .map_err(|_| format!("invalid number {}", &rest[..end]))?;
+            tokens.push(Token::Number(number));
+            rest = &rest[end..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Variable(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            return Err(format!("unexpected {c} in filter"));
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+// A recursive descent parser over the tokens of a filter.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn accept(&mut self, token: &Token) -> bool {
+        let accepted = self.tokens.get(self.position) == Some(token);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.accept(&Token::Operator("||")) {
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.term()?;
+        while self.accept(&Token::Operator("&&")) {
+            expression = Expression::And(Box::new(expression), Box::new(self.term()?));
+        }
+        Ok(expression)
+    }
+
+    fn term(&mut self) -> Result<Expression, String> {
+        if self.accept(&Token::Operator("!")) {
+            return Ok(Expression::Not(Box::new(self.term()?)));
+        }
+        if self.accept(&Token::Open) {
+            let expression = self.or()?;
+            if !self.accept(&Token::Close) {
+                return Err(String::from("unmatched ( in filter"));
+            }
+            return Ok(expression);
+        }
+        let left = self.value()?;
+        let operator = match self.next() {
+            Some(Token::Operator("<")) => Operator::Less,
+            Some(Token::Operator("<=")) => Operator::LessEqual,
+            Some(Token::Operator(">")) => Operator::Greater,
+            Some(Token::Operator(">=")) => Operator::GreaterEqual,
+            Some(Token::Operator("==")) => Operator::Equal,
+            Some(Token::Operator("!=")) => Operator::NotEqual,
+            _ => {
+                return Err(String::from(
+                    "expected a comparison (<, <=, >, >=, ==, or !=)",
+                ))
+            }
+        };
+        let right = self.value()?;
+        Ok(Expression::Compare(left, operator, right))
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Value::Number(number)),
+            Some(Token::Variable(variable)) => match variable.as_str() {
+                "len" | "length" => Ok(Value::Length),
+                "gc" => Ok(Value::Gc),
+                "n_count" => Ok(Value::NCount),
+                _ => Err(format!(
+                    "unknown variable {variable} (expected len, gc, or n_count)"
+                )),
+            },
+            _ => Err(String::from("expected a number or variable")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(expression: &str, sequence: &[u8]) -> bool {
+        Filter::parse(expression).unwrap().matches(sequence)
+    }
+
+    fn error(expression: &str) -> String {
+        Filter::parse(expression).err().unwrap()
+    }
+
+    #[test]
+    fn each_operator_compares() {
+        // 8 bases, half G or C, one N.
+        let sequence = b"ACGTNgca";
+        assert!(matches("len < 9", sequence));
+        assert!(!matches("len < 8", sequence));
+        assert!(matches("len <= 8", sequence));
+        assert!(!matches("len <= 7", sequence));
+        assert!(matches("gc > 0.4", sequence));
+        assert!(!matches("gc > 0.5", sequence));
+        assert!(matches("gc >= 0.5", sequence));
+        assert!(!matches("gc >= 0.6", sequence));
+        assert!(matches("n_count == 1", sequence));
+        assert!(!matches("n_count == 0", sequence));
+        assert!(matches("length != 7", sequence));
+        assert!(!matches("length != 8", sequence));
+    }
+
+    #[test]
+    fn expressions_combine() {
+        let sequence = b"GGCCNNAT";
+        assert!(matches("gc > 0.4 && len > 4", sequence));
+        assert!(!matches("gc > 0.6 && len > 4", sequence));
+        assert!(matches("gc > 0.6 || n_count >= 2", sequence));
+        assert!(matches("!(n_count > 2)", sequence));
+        // && binds tighter than ||.
+        assert!(matches("len == 1 && gc == 1 || len == 8", sequence));
+        assert!(!matches("len == 1 && (gc == 1 || len == 8)", sequence));
+        assert!(matches("gc == 0", b""));
+    }
+
+    #[test]
+    fn malformed_filters_are_errors() {
+        assert_eq!(error("gc = 0.5"), "use == to compare for equality");
+        assert_eq!(
+            error("at > 0.5"),
+            "unknown variable at (expected len, gc, or n_count)"
+        );
+        assert_eq!(
+            error("len 5"),
+            "expected a comparison (<, <=, >, >=, ==, or !=)"
+        );
+        assert_eq!(error("len >"), "expected a number or variable");
+        assert_eq!(error("(len > 5"), "unmatched ( in filter");
+        assert_eq!(
+            error("len > 5 len"),
+            "unexpected text after the end of the filter"
+        );
+        assert_eq!(error("len > 1.2.3"), "invalid number 1.2.3");
+        assert_eq!(error("len > 5 & gc > 0"), "unexpected & in filter");
+    }
+}
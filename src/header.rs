@@ -0,0 +1,111 @@
+use std::fmt::Write;
+
+use noodles::core::Region;
+
+// A piece of a header template: literal text, or a placeholder.
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Name,
+    Region,
+    Contig,
+    Start,
+    End,
+    Strand,
+    Length,
+}
+
+// A template for the definition line (or name) of each output record, with
+// placeholders in braces for the record's name, region, the region's
+// contig, start, and end, strand, and length (e.g., "{name}|{region}|{strand}"
+// or "{contig}_{start}_{end}"). Braces are written literally when doubled
+// ("{{" and "}}").
+#[derive(Clone)]
+pub struct HeaderTemplate(Vec<Piece>);
+
+impl HeaderTemplate {
+    // Parse a template, failing on unknown placeholders and unmatched braces,
+    // so a mistyped template is reported before anything is extracted.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        return Err(String::from("unmatched { in header template"));
+                    };
+                    let piece = match &rest[..end] {
+                        "name" => Piece::Name,
+                        "region" => Piece::Region,
+                        "contig" => Piece::Contig,
+                        "start" => Piece::Start,
+                        "end" => Piece::End,
+                        "strand" => Piece::Strand,
+                        "length" => Piece::Length,
+                        placeholder => {
+                            return Err(format!(
+                                "unknown placeholder {{{placeholder}}} (expected {{name}}, {{region}}, {{contig}}, {{start}}, {{end}}, {{strand}}, or {{length}})"
+                            ))
+                        }
+                    };
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(piece);
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => return Err(String::from("unmatched } in header template")),
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Self(pieces))
+    }
+
+    // Parse a template for record names, which can't contain whitespace,
+    // since it would end the name.
+    pub fn parse_name(template: &str) -> Result<Self, String> {
+        if template.contains(char::is_whitespace) {
+            return Err(String::from("record names can't contain whitespace"));
+        }
+        Self::parse(template)
+    }
+
+    // Fill in the template for a record. If a region is unbounded (e.g., a
+    // whole contig), its start is 1 and its end is the last base of the
+    // record.
+    pub fn render(&self, name: &str, region: &Region, strand: char, length: usize) -> String {
+        let interval = region.interval();
+        let start = interval.start().map_or(1, usize::from);
+        let end = interval
+            .end()
+            .map_or((start + length).saturating_sub(1), usize::from);
+        let mut header = String::new();
+        for piece in &self.0 {
+            let _ = match piece {
+                Piece::Text(text) => write!(header, "{text}"),
+                Piece::Name => write!(header, "{name}"),
+                Piece::Region => write!(header, "{region}"),
+                Piece::Contig => write!(header, "{}", region.name()),
+                Piece::Start => write!(header, "{start}"),
+                Piece::End => write!(header, "{end}"),
+                Piece::Strand => write!(header, "{strand}"),
+                Piece::Length => write!(header, "{length}"),
+            };
+        }
+        header
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom, Write},
+    str,
+};
+
+use memchr::memchr_iter;
+use noodles::{
+    bgzf::gzi,
+    fasta::{self, fai},
+};
+use rayon::prelude::*;
+
+// Build a FASTA index from an uncompressed stream. `fasta::index` only
+// accepts a path to a plaintext file, so this is used wherever the bytes
+// have to be decoded first (e.g., a bgzipped reference).
+pub fn index_reader<R: BufRead>(mut reader: R) -> io::Result<fai::Index> {
+    let mut index = Vec::new();
+    let mut line = Vec::new();
+    let mut offset = 0;
+
+    // The record currently being indexed: name, sequence offset, length,
+    // bases per line, bytes per line, and whether a short (last) line has
+    // already been seen.
+    let mut current: Option<(String, u64, u64, u64, u64, bool)> = None;
+
+    loop {
+        line.clear();
+        let bytes_read = reader.read_until(b'\n', &mut line)? as u64;
+        if bytes_read == 0 {
+            break;
+        }
+        offset += bytes_read;
+
+        if line.starts_with(b">") {
+            if let Some(record) = current.take() {
+                index.push(finish_record(record)?);
+            }
+            let definition = str::from_utf8(&line[1..])
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+            let name = definition
+                .split_ascii_whitespace()
+                .next()
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "missing name"))?;
+            current = Some((name.to_string(), offset, 0, 0, 0, false));
+            continue;
+        }
+
+        let Some((name, _, length, line_bases, line_width, short_line)) = current.as_mut() else {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "sequence data before first definition",
+            ));
+        };
+
+        let bases = line
+            .strip_suffix(b"\n")
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .unwrap_or(&line)
+            .len() as u64;
+        if bases == 0 {
+            *short_line = true;
+            continue;
+        }
+
+        if *line_bases == 0 {
+            *line_bases = bases;
+            *line_width = bytes_read;
+        } else if *short_line || bases > *line_bases {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{name}: inconsistent line length"),
+            ));
+        } else if bases < *line_bases {
+            *short_line = true;
+        }
+        *length += bases;
+    }
+
+    if let Some(record) = current.take() {
+        index.push(finish_record(record)?);
+    }
+    Ok(index)
+}
+
+fn finish_record(
+    (name, offset, length, line_bases, line_width, _): (String, u64, u64, u64, u64, bool),
+) -> io::Result<fai::Record> {
+    if length == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{name}: empty sequence"),
+        ));
+    }
+    Ok(fai::Record::new(
+        name, length, offset, line_bases, line_width,
+    ))
+}
+
+// Index a plaintext FASTA file on the current rayon thread pool, giving
+// the same index as `fasta::index`. The file is split into a chunk per
+// thread, which are scanned for definition lines (lines starting with >),
+// then the records between them are indexed in parallel, each with its
+// offset in the file. Records are checked as `fasta::index` checks them
+// (every line but the last has the same number of bases and bytes), and a
+// file that fails any check is indexed by `fasta::index` instead, so it
+// fails with the same error.
+pub fn index_parallel(path: &str, threads: usize) -> io::Result<fai::Index> {
+    let size = fs::metadata(path)?.len();
+    let chunk_size = size.div_ceil(threads.max(1) as u64).max(1);
+    let chunks: Vec<u64> = (0..size).step_by(chunk_size as usize).collect();
+    let definitions = chunks
+        .par_iter()
+        .map(|&start| find_definitions(path, start, (start + chunk_size).min(size)))
+        .collect::<io::Result<Vec<_>>>()?
+        .concat();
+    if definitions.first() != Some(&0) {
+        return fasta::index(path);
+    }
+
+    let ends = definitions.iter().skip(1).copied().chain([size]);
+    let records: Vec<(u64, u64)> = definitions.iter().copied().zip(ends).collect();
+    match records
+        .par_iter()
+        .map(|&(start, end)| index_record(path, start, end))
+        .collect()
+    {
+        Ok(index) => Ok(index),
+        Err(_) => fasta::index(path),
+    }
+}
+
+// Return the offset of each line starting with > that starts between start
+// and end, reading from the byte before start to tell whether a line starts
+// there.
+fn find_definitions(path: &str, start: u64, end: u64) -> io::Result<Vec<u64>> {
+    let mut file = File::open(path)?;
+    let from = start.saturating_sub(1);
+    file.seek(SeekFrom::Start(from))?;
+    let mut reader = BufReader::with_capacity(1 << 20, file.take(end - from));
+    let mut line_start = start == 0;
+    let mut offset = from;
+    let mut definitions = Vec::new();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        if line_start && buf[0] == b'>' {
+            definitions.push(offset);
+        }
+        for i in memchr_iter(b'\n', buf) {
+            if buf.get(i + 1) == Some(&b'>') {
+                definitions.push(offset + i as u64 + 1);
+            }
+        }
+        line_start = buf.last() == Some(&b'\n');
+        let n = buf.len();
+        offset += n as u64;
+        reader.consume(n);
+    }
+    Ok(definitions)
+}
+
+// Index the record between start and end, from its definition line to the
+// end of its last sequence line.
+fn index_record(path: &str, start: u64, end: u64) -> io::Result<fai::Record> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(start))?;
+    let mut reader = BufReader::with_capacity(1 << 20, file.take(end - start));
+
+    let mut line = Vec::new();
+    let offset = start + reader.read_until(b'\n', &mut line)? as u64;
+    let definition =
+        str::from_utf8(&line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    let name = definition[1..]
+        .trim_end_matches('\n')
+        .trim_end_matches('\r')
+        .split(|c: char| c.is_ascii_whitespace())
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| invalid("missing name"))?
+        .to_string();
+
+    // Every line but the last has the bytes and bases of the first.
+    let mut first: Option<(u64, u64)> = None;
+    let mut last: Option<(u64, u64)> = None;
+    let mut length = 0;
+    loop {
+        line.clear();
+        let bytes_read = reader.read_until(b'\n', &mut line)? as u64;
+        if bytes_read == 0 {
+            break;
+        }
+        let bases = line.strip_suffix(b"\n").unwrap_or(&line);
+        let bases = bases.strip_suffix(b"\r").unwrap_or(bases).len() as u64;
+        if last.is_some() && last != first {
+            return Err(invalid("inconsistent line length"));
+        }
+        first.get_or_insert((bytes_read, bases));
+        last = Some((bytes_read, bases));
+        length += bases;
+    }
+    let Some((line_width, line_bases)) = first.filter(|_| length > 0) else {
+        return Err(invalid("empty sequence"));
+    };
+    Ok(fai::Record::new(
+        name, length, offset, line_bases, line_width,
+    ))
+}
+
+// Copy a FASTA index; fai records don't implement Clone.
+pub fn clone_index(index: &fai::Index) -> fai::Index {
+    index
+        .iter()
+        .map(|record| {
+            fai::Record::new(
+                record.name(),
+                record.length(),
+                record.offset(),
+                record.line_bases(),
+                record.line_width(),
+            )
+        })
+        .collect()
+}
+
+// Build a gzip index by walking the BGZF block headers. Each entry is the
+// compressed and uncompressed offset of a block after the first, which is
+// the layout `bgzip -i` writes.
+pub fn build_gzi<R: Read>(mut reader: R) -> io::Result<gzi::Index> {
+    let mut index = Vec::new();
+    let (mut compressed, mut uncompressed) = (0, 0);
+    let mut header = [0; 12];
+
+    loop {
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if header[..4] != [0x1f, 0x8b, 0x08, 0x04] {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "invalid BGZF header",
+            ));
+        }
+
+        // Find the block size in the BC subfield of the extra field.
+        let xlen = u16::from_le_bytes([header[10], header[11]]) as usize;
+        let mut extra = vec![0; xlen];
+        reader.read_exact(&mut extra)?;
+        let mut block_size = None;
+        let mut subfields = &extra[..];
+        while subfields.len() >= 4 {
+            let length = u16::from_le_bytes([subfields[2], subfields[3]]) as usize;
+            if subfields[..2] == *b"BC" && length == 2 && subfields.len() >= 6 {
+                block_size = Some(u16::from_le_bytes([subfields[4], subfields[5]]) as usize + 1);
+            }
+            subfields = &subfields[(4 + length).min(subfields.len())..];
+        }
+        let block_size = block_size
+            .filter(|&size| size >= header.len() + xlen + 8)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "invalid BGZF block size"))?;
+
+        // The rest of the block is compressed data, CRC32, and ISIZE.
+        let mut rest = vec![0; block_size - header.len() - xlen];
+        reader.read_exact(&mut rest)?;
+        let isize = &rest[rest.len() - 4..];
+
+        if compressed > 0 {
+            index.push((compressed, uncompressed));
+        }
+        compressed += block_size as u64;
+        uncompressed += u32::from_le_bytes([isize[0], isize[1], isize[2], isize[3]]) as u64;
+    }
+
+    Ok(index)
+}
+
+pub fn write_gzi<W: Write>(mut writer: W, index: &gzi::Index) -> io::Result<()> {
+    writer.write_all(&(index.len() as u64).to_le_bytes())?;
+    for (compressed, uncompressed) in index {
+        writer.write_all(&compressed.to_le_bytes())?;
+        writer.write_all(&uncompressed.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Serialize an index as a .fai file.
+    fn fai_bytes(index: &fai::Index) -> Vec<u8> {
+        let mut writer = fai::Writer::new(Vec::new());
+        writer.write_index(index).unwrap();
+        writer.get_ref().clone()
+    }
+
+    #[test]
+    fn parallel_indexes_match_fasta_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ref.fa");
+        // Four records of 16 bytes each, so chunks of 16 or 32 bytes end
+        // with a record's last newline and the next chunk starts with >,
+        // and other thread counts split records (and lines) between chunks.
+        fs::write(
+            &path,
+            ">a\nACGTACGTACGT\n>b\nACGTAC\nGTACG\n>c desc\nACGTA\nC\n>dd\nAC\nGT\nAC\nGT\n",
+        )
+        .unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 64);
+        let path = path.to_string_lossy();
+        let expected = fai_bytes(&fasta::index(&*path).unwrap());
+
+        for threads in [1, 2, 3, 4, 5, 7, 8, 16, 64, 100] {
+            // Each definition line is found once, by the chunk it starts in.
+            let chunk_size = 64usize.div_ceil(threads);
+            let definitions: Vec<u64> = (0..64)
+                .step_by(chunk_size)
+                .flat_map(|start| {
+                    find_definitions(&path, start as u64, (start + chunk_size).min(64) as u64)
+                        .unwrap()
+                })
+                .collect();
+            assert_eq!(definitions, [0, 16, 32, 48], "{threads} threads");
+
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let index = pool.install(|| index_parallel(&path, threads)).unwrap();
+            assert_eq!(
+                String::from_utf8(fai_bytes(&index)).unwrap(),
+                String::from_utf8(expected.clone()).unwrap(),
+                "{threads} threads"
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Extract regions from FASTA files.
+
+#[cfg(feature = "bam")]
+mod bam;
+mod binpacked;
+mod checksum;
+mod consensus;
+mod diagnostic;
+mod filter;
+mod header;
+mod index;
+mod multi;
+#[cfg(feature = "remote")]
+mod remote;
+mod sequences;
+#[cfg(feature = "serve")]
+mod serve;
+mod translate;
+mod twobit;
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+pub use binpacked::read_record as read_binpacked;
+pub use checksum::Checksum;
+pub use consensus::Variant;
+pub use diagnostic::Diagnostic;
+pub use filter::Filter;
+pub use header::HeaderTemplate;
+pub use noodles::{core::Region, fasta::Record};
+pub use sequences::{
+    Alphabet, Case, ContigNaming, Coordinates, ExtractOptions, OutputFormat, OutputOptions,
+    Progress, RegionFormat, Sequences, SortOrder, Stats,
+};
+#[cfg(feature = "serve")]
+pub use serve::{query, serve};
+pub use translate::{codon_table, translate};
+
+// Extract regions from a FASTA file, returning one record per region in
+// the order given. The FASTA is indexed first if it has no index.
+pub fn extract_regions(
+    fasta: &Path,
+    regions: &[Region],
+    opts: ExtractOptions,
+) -> Result<Vec<Record>> {
+    let fasta = fasta.to_str().context("FASTA path is not valid UTF-8")?;
+    let regions = regions
+        .iter()
+        .map(|region| (region.clone(), false, None))
+        .collect();
+    let mut sequences = Sequences::from_regions(fasta, regions)?;
+    sequences.extract(opts)?;
+    Ok(sequences.into_records())
+}
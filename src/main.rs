@@ -1,21 +1,257 @@
-use clap::Parser;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use clap::{CommandFactory, Parser};
 
 mod cli;
-mod sequences;
 
-use anyhow::Result;
-use cli::Cli;
-use sequences::Sequences;
+use anyhow::{bail, Result};
+use cli::{Cli, Command, ExtractArgs};
+use extract::{Coordinates, Diagnostic, Region, RegionFormat, Sequences};
+use indicatif::{ProgressBar, ProgressStyle};
 
 fn main() -> Result<()> {
-    // Parse CLI arguments
-    let args = Cli::parse();
-    let (fasta_file, region_file) = args.get_input();
-    let (output_location, merge, contig_name, gap_size) = args.get_output();
-
-    // Create Sequences struct; extract sequences; write output.
-    let mut sequences = Sequences::new(&fasta_file, &region_file)?;
-    sequences.extract()?;
-    sequences.write(output_location, merge, contig_name, gap_size)?;
+    // Parse CLI arguments and run the subcommand.
+    match Cli::parse().into_command() {
+        Command::Extract(args) => run_extract(*args),
+        Command::Index { fasta } => Sequences::index_fasta(&fasta),
+        Command::Stats { fasta } => contig_stats(&fasta),
+        Command::Check {
+            fasta,
+            regions,
+            region_format,
+            coords,
+            circular,
+        } => check_regions(
+            &fasta,
+            &regions,
+            region_format.map(|format| format.0),
+            coords.0,
+            circular,
+        ),
+        #[cfg(feature = "serve")]
+        Command::Serve { fasta, socket } => {
+            extract::serve(&fasta, &socket, |diagnostic| eprintln!("{diagnostic}"))
+        }
+        #[cfg(feature = "serve")]
+        Command::Query { socket, regions } => extract::query(&socket, &regions),
+        Command::Completions { shell } => {
+            // Completions are generated into a buffer first, since
+            // generating straight to stdout panics if it's closed early
+            // (e.g., piped to head).
+            let mut command = Cli::command();
+            let name = command.get_name().to_string();
+            let mut completions = Vec::new();
+            clap_complete::generate(shell, &mut command, name, &mut completions);
+            io::stdout().lock().write_all(&completions)?;
+            Ok(())
+        }
+    }
+}
+
+fn run_extract(args: ExtractArgs) -> Result<()> {
+    let (fasta_file, region_files, region_format, coords, strict) = args.get_input();
+    let mut features = match args.get_features() {
+        Some((feature_file, feature_type)) => {
+            Sequences::read_features(&feature_file, &feature_type)?
+        }
+        None => Vec::new(),
+    };
+    if let Some(vcf_file) = args.get_vcf() {
+        features.extend(Sequences::read_variants(&vcf_file)?);
+    }
+    if let Some((bam_file, soft_clips)) = args.get_bam() {
+        features.extend(Sequences::read_alignments(&bam_file, soft_clips)?);
+    }
+    for pattern in args.get_patterns() {
+        features.push((Region::new(pattern, ..), false, None));
+    }
+    let name_map = match args.get_name_map() {
+        Some(name_map_file) => Sequences::read_name_map(&name_map_file)?,
+        None => HashMap::new(),
+    };
+
+    // List the parsed regions without extracting them, if requested.
+    let mut diagnostics = Vec::new();
+    if args.list_regions() {
+        let mut regions = Sequences::read_regions(
+            &region_files,
+            region_format,
+            coords,
+            strict,
+            &mut diagnostics,
+        )?;
+        report(diagnostics);
+        regions.extend(features);
+        if let Some((size, seed)) = args.get_sample() {
+            Sequences::sample_regions(&mut regions, size, seed);
+        }
+        Sequences::resolve_names(&mut regions, &name_map);
+        return list_regions(regions);
+    }
+
+    let mut extract_options = args.get_extract();
+    extract_options.name_map = name_map;
+    if let Some(alias_file) = args.get_chr_alias() {
+        extract_options.chr_alias = Sequences::read_chr_alias(&alias_file)?;
+    }
+    if let Some(md5_file) = args.get_contig_md5() {
+        extract_options.contig_md5 = Sequences::read_contig_md5(&md5_file)?;
+    }
+    if let Some(mask_file) = args.get_mask() {
+        let mask = Sequences::read_regions(&[mask_file], None, coords, strict, &mut diagnostics)?;
+        extract_options.mask = mask.into_iter().map(|(region, _, _)| region).collect();
+    }
+    if let Some(table_file) = args.get_complement_table() {
+        extract_options.complement_table = Sequences::read_complement_table(&table_file)?;
+    }
+    if let Some(exclude_file) = args.get_exclude() {
+        let exclude =
+            Sequences::read_regions(&[exclude_file], None, coords, strict, &mut diagnostics)?;
+        extract_options.exclude = exclude.into_iter().map(|(region, _, _)| region).collect();
+    }
+    if let Some(vcf_file) = args.get_consensus() {
+        extract_options.consensus = Sequences::read_consensus(&vcf_file)?;
+    }
+    let mut output_options = args.get_output();
+    if let Some(order_file) = args.get_order() {
+        output_options.order = Sequences::read_order(&order_file)?;
+    }
+
+    report(diagnostics);
+
+    // Show how many regions have been extracted on stderr, if requested
+    // (and stderr is a terminal).
+    let progress = if args.progress() {
+        let progress = ProgressBar::new(0);
+        progress.set_style(ProgressStyle::with_template(
+            "{bar:40} {pos}/{len} regions ({elapsed}, {eta} left)",
+        )?);
+        let bar = progress.clone();
+        extract_options.progress = Some(Box::new(move |done, total| {
+            bar.set_length(total as u64);
+            bar.set_position(done as u64);
+        }));
+        progress
+    } else {
+        ProgressBar::hidden()
+    };
+
+    // Create Sequences struct; extract sequences; write output. Warnings
+    // and notes are printed after each step, even if it fails.
+    let mut sequences = Sequences::new(&fasta_file, &region_files, region_format, coords, strict)?;
+    sequences.add_regions(features);
+    if args.count() {
+        let counts = sequences.count_bases(extract_options);
+        report(sequences.take_diagnostics());
+        let mut stdout = io::stdout().lock();
+        for (contig, bases) in counts? {
+            writeln!(stdout, "{contig}\t{bases}")?;
+        }
+        return Ok(());
+    }
+    if args.pipeline() {
+        let extracted = sequences.extract_pipelined(extract_options, output_options);
+        report(sequences.take_diagnostics());
+        return extracted;
+    }
+    let extracted = sequences.extract(extract_options);
+    progress.finish();
+    report(sequences.take_diagnostics());
+    extracted?;
+    let written = sequences.write(output_options);
+    report(sequences.take_diagnostics());
+    written?;
+    if args.stats() {
+        print_stats(&sequences);
+    }
+    Ok(())
+}
+
+// Print warnings and notes to stderr.
+fn report(diagnostics: Vec<Diagnostic>) {
+    for diagnostic in diagnostics {
+        eprintln!("{diagnostic}");
+    }
+}
+
+// Print a summary of the extraction to stderr.
+fn print_stats(sequences: &Sequences) {
+    let stats = sequences.stats();
+    eprintln!("regions requested: {}", stats.requested);
+    eprintln!("regions extracted: {}", stats.extracted);
+    eprintln!("reverse complemented: {}", stats.reversed);
+    eprintln!("bases extracted: {}", stats.bases);
+    eprintln!("GC content: {:.2}%", stats.gc_content);
+    if let Some(trimmed) = stats.trimmed {
+        eprintln!("Ns trimmed: {trimmed}");
+    }
+    if let Some(gap_spanning) = stats.gap_spanning {
+        eprintln!("regions spanning gaps: {gap_spanning}");
+    }
+}
+
+// Print the name and length of each contig in the FASTA index.
+fn contig_stats(fasta_file: &str) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    let mut diagnostics = Vec::new();
+    let lengths = Sequences::contig_lengths(fasta_file, &mut diagnostics);
+    report(diagnostics);
+    for (name, length) in lengths? {
+        writeln!(stdout, "{name}\t{length}")?;
+    }
+    Ok(())
+}
+
+// Print each region in the regions files with its status, failing if any
+// region has a problem. Regions that can't be parsed are always an error.
+fn check_regions(
+    fasta_file: &str,
+    region_files: &[String],
+    region_format: Option<RegionFormat>,
+    coords: Coordinates,
+    circular: bool,
+) -> Result<()> {
+    let mut diagnostics = Vec::new();
+    let regions =
+        Sequences::read_regions(region_files, region_format, coords, true, &mut diagnostics)?;
+    let statuses = Sequences::check_regions(fasta_file, regions, circular, &mut diagnostics);
+    report(diagnostics);
+    let statuses = statuses?;
+    let mut stdout = io::stdout().lock();
+    for (region, status) in &statuses {
+        writeln!(stdout, "{region}\t{status}")?;
+    }
+    let problems = statuses.iter().filter(|(_, status)| status != "ok").count();
+    if problems > 0 {
+        bail!("{problems} of {} regions have problems", statuses.len());
+    }
+    Ok(())
+}
+
+// Print each parsed region with its reference name, 1-based coords,
+// length, and strand, without reading the FASTA. Unbounded coords
+// (e.g., a whole contig) are printed as '.'.
+fn list_regions(regions: Vec<(Region, bool, Option<String>)>) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (region, reversed, _) in regions {
+        let start = region.interval().start().map(usize::from).unwrap_or(1);
+        let end = region.interval().end().map(usize::from);
+        // The length of a region wrapping around a circular contig depends
+        // on the contig's length.
+        let (end, length) = match end {
+            Some(end) if end >= start => (end.to_string(), (end + 1 - start).to_string()),
+            Some(end) => (end.to_string(), String::from(".")),
+            None => (String::from("."), String::from(".")),
+        };
+        let strand = if reversed { '-' } else { '+' };
+        writeln!(
+            stdout,
+            "{}\t{start}\t{end}\t{length}\t{strand}",
+            region.name()
+        )?;
+    }
     Ok(())
 }
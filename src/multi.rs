@@ -0,0 +1,104 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Seek, SeekFrom},
+};
+
+use anyhow::{bail, Result};
+use noodles::fasta::{self, fai, io::BufReadSeek, IndexedReader};
+
+// Join indexed FASTA files (e.g., a main assembly and a file of alt or decoy
+// contigs) into a single indexed reader, as if they were one file. Each
+// file's contigs are given offsets past the end of the files before it, so
+// a query for a contig is routed to the file it's in. A contig name in more
+// than one file would be ambiguous, so it's an error.
+pub fn join(
+    files: Vec<(&str, IndexedReader<Box<dyn BufReadSeek>>)>,
+) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut index = Vec::new();
+    let mut readers = Vec::with_capacity(files.len());
+    let mut base = 0;
+    for (file, reader) in files {
+        let mut end = 0;
+        for record in reader.index() {
+            if let Some(other) = seen.insert(record.name().to_string(), file) {
+                bail!("contig {} is in both {other} and {file}", record.name());
+            }
+            index.push(fai::Record::new(
+                record.name(),
+                record.length(),
+                base + record.offset(),
+                record.line_bases(),
+                record.line_width(),
+            ));
+            // The bases of a record end before this many bytes past its
+            // offset, however its last line ends.
+            let lines = record.length().div_ceil(record.line_bases().max(1));
+            end = end.max(record.offset() + lines * record.line_width());
+        }
+        readers.push((base, reader.into_inner()));
+        base += end + 1;
+    }
+    let reader: Box<dyn BufReadSeek> = Box::new(JoinedReader {
+        readers,
+        current: 0,
+        position: 0,
+    });
+    Ok(fasta::indexed_reader::Builder::default()
+        .set_index(index)
+        .build_from_reader(reader)?)
+}
+
+// A reader of several files joined end to end, each starting at an offset
+// (its base). Seeking picks the file an offset is in, and reading stops at
+// the end of that file.
+struct JoinedReader {
+    readers: Vec<(u64, Box<dyn BufReadSeek>)>,
+    current: usize,
+    position: u64,
+}
+
+impl Read for JoinedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for JoinedReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.readers[self.current].1.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.readers[self.current].1.consume(amt);
+        self.position += amt as u64;
+    }
+}
+
+impl Seek for JoinedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative position or from the end of joined files",
+            )
+        })?;
+        self.current = self
+            .readers
+            .partition_point(|(base, _)| *base <= position)
+            .saturating_sub(1);
+        let (base, reader) = &mut self.readers[self.current];
+        reader.seek(SeekFrom::Start(position - *base))?;
+        self.position = position;
+        Ok(position)
+    }
+}
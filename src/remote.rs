@@ -0,0 +1,149 @@
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use anyhow::{bail, Context, Result};
+use noodles::fasta::{self, fai, io::BufReadSeek, IndexedReader};
+use reqwest::{
+    blocking::Client,
+    header::{CONTENT_RANGE, RANGE},
+    StatusCode,
+};
+
+// The number of bytes fetched by each range request. Reading a block at a
+// time saves a request per line of a region, and nearby regions are often
+// in the same block.
+const BLOCK_SIZE: u64 = 1 << 20;
+
+// Open a FASTA file served over HTTP(S) for querying, with the .fai index
+// next to it (e.g., https://example.org/ref.fa.fai). The index is
+// downloaded and the FASTA file is read with range requests, so only the
+// blocks of the regions are downloaded rather than the whole file. Bgzipped
+// FASTA files aren't supported, since they need a .gzi index too.
+pub fn open(url: &str) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
+    if url.ends_with(".gz") || url.ends_with(".bgz") {
+        bail!("bgzipped FASTA files can't be read from URLs: {url}");
+    }
+    let client = Client::new();
+
+    let index_url = format!("{url}.fai");
+    let index = client
+        .get(&index_url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.bytes())
+        .with_context(|| format!("could not download {index_url}"))?;
+    let index = fai::Reader::new(&index[..])
+        .read_index()
+        .with_context(|| format!("invalid index {index_url}"))?;
+
+    let reader: Box<dyn BufReadSeek> = Box::new(HttpReader::new(client, url)?);
+    Ok(fasta::indexed_reader::Builder::default()
+        .set_index(index)
+        .build_from_reader(reader)?)
+}
+
+// A reader of a file served over HTTP(S), fetching a block at a time with
+// range requests as it's read, and seeking without any requests.
+struct HttpReader {
+    client: Client,
+    url: String,
+    length: u64,
+    position: u64,
+    block: Vec<u8>,
+    block_start: u64,
+}
+
+impl HttpReader {
+    // Check that the server answers range requests, and get the length of
+    // the file from the range of the first byte.
+    fn new(client: Client, url: &str) -> Result<Self> {
+        let response = client
+            .get(url)
+            .header(RANGE, "bytes=0-0")
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("could not read {url}"))?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            bail!("{url} doesn't support range requests");
+        }
+        let length = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, length)| length.parse().ok())
+            .with_context(|| format!("{url} didn't give its length"))?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            length,
+            position: 0,
+            block: Vec::new(),
+            block_start: 0,
+        })
+    }
+
+    // Fetch the block starting at the current position.
+    fn fetch(&mut self) -> io::Result<()> {
+        let end = (self.position + BLOCK_SIZE).min(self.length) - 1;
+        let response = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-{end}", self.position))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(io::Error::other)?;
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return Err(io::Error::other(format!(
+                "{} didn't answer a range request",
+                self.url
+            )));
+        }
+        self.block = response.bytes().map_err(io::Error::other)?.to_vec();
+        self.block_start = self.position;
+        Ok(())
+    }
+}
+
+impl Read for HttpReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for HttpReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.position >= self.length {
+            return Ok(&[]);
+        }
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.position < self.block_start || self.position >= block_end {
+            self.fetch()?;
+        }
+        Ok(&self.block[(self.position - self.block_start) as usize..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position += amt as u64;
+    }
+}
+
+impl Seek for HttpReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
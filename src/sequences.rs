@@ -1,190 +1,2515 @@
 use std::{
     collections::HashMap,
-    fs::{read_to_string, File},
-    io::{self, Write},
+    io::{Read, Seek, SeekFrom},
     path::Path,
     str,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use noodles::{
-    core::{Position, Region},
-    fasta::{self as fasta, fai, io::BufReadSeek, record::Sequence, IndexedReader, Record},
+    core::{region::Interval, Position, Region},
+    fasta::{self as fasta, io::BufReadSeek, record::Sequence, IndexedReader, Record},
 };
+use rand::{rngs::StdRng, SeedableRng};
+use rayon::{prelude::*, ThreadPoolBuilder};
+use regex::Regex;
+
+use crate::{
+    checksum::Checksum,
+    consensus::{self, Variant},
+    filter::Filter,
+    header::HeaderTemplate,
+    index, translate, Diagnostic,
+};
+
+mod output;
+mod reader;
+mod regions;
+
+pub use output::{OutputFormat, OutputOptions, SortOrder};
+pub use regions::{Coordinates, RegionFormat};
+
+use reader::is_url;
+
+// The alphabet of the sequences in the FASTA file.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Alphabet {
+    // DNA
+    #[default]
+    Dna,
+    // RNA, complemented with U instead of T
+    Rna,
+    // amino acids, which can't be complemented or translated, and are masked and padded with X
+    Protein,
+}
+
+impl Alphabet {
+    // The character for an unknown residue, used to mask and pad sequences.
+    fn unknown(self) -> u8 {
+        match self {
+            Alphabet::Dna | Alphabet::Rna => b'N',
+            Alphabet::Protein => b'X',
+        }
+    }
+}
+
+// A contig naming convention to rename regions' contigs to when the FASTA
+// index names them the other way.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ContigNaming {
+    // UCSC names, with a chr prefix (chr1, chrM)
+    Ucsc,
+    // Ensembl names, without one (1, MT)
+    Ensembl,
+}
+
+impl ContigNaming {
+    // The name of a contig in this convention, if it's different.
+    fn rename(self, name: &str) -> Option<String> {
+        match self {
+            ContigNaming::Ucsc if name == "MT" => Some(String::from("chrM")),
+            ContigNaming::Ucsc if !name.starts_with("chr") => Some(format!("chr{name}")),
+            ContigNaming::Ensembl if name == "chrM" => Some(String::from("MT")),
+            ContigNaming::Ensembl => name.strip_prefix("chr").map(str::to_string),
+            ContigNaming::Ucsc => None,
+        }
+    }
+}
+
+// A span of a contig read into memory: the contig's name, the 1-based
+// position of the first base, and the bases.
+type Span = (String, usize, Vec<u8>);
+
+// An extracted record with the region it was extracted from (as extracted,
+// e.g., after flanking) and whether it was reverse complemented.
+type Extracted = (Region, bool, Record);
+
+// The records extracted from a cluster of regions, by the index of each
+// region, with the warnings and notes from extracting them.
+type ExtractedCluster = (Vec<(usize, Extracted)>, Vec<Diagnostic>);
+
+// The blocks (e.g., exons) of regions read from BED12 lines, in genomic
+// order, by each region's string and name. Strands aren't part of the key,
+// since they can be overridden after the blocks are read.
+type Blocks = HashMap<(String, Option<String>), Vec<Region>>;
+
+// A case to normalize extracted sequences to.
+#[derive(Clone, Copy)]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+// A callback reporting progress as regions are extracted: the number of
+// regions extracted so far and the total, starting from none.
+pub type Progress = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+// The options for extracting regions, as described in `Sequences::extract`.
+#[derive(Default)]
+pub struct ExtractOptions {
+    pub case: Option<Case>,
+    pub translate: bool,
+    pub frame: usize,
+    pub annotate: bool,
+    pub threads: usize,
+    pub revcomp_all: bool,
+    pub no_revcomp: bool,
+    pub clamp: bool,
+    pub flank_left: usize,
+    pub flank_right: usize,
+    pub max_region_length: Option<usize>,
+    pub reverse_only: bool,
+    pub mask: Vec<Region>,
+    pub exclude: Vec<Region>,
+    pub region_names: bool,
+    pub min_length: Option<usize>,
+    pub circular: bool,
+    pub name_map: HashMap<String, (Region, bool)>,
+    pub hard_mask: bool,
+    pub merge_overlaps: bool,
+    pub consensus: HashMap<String, Vec<Variant>>,
+    pub no_index: bool,
+    pub progress: Option<Progress>,
+    pub pad_to_length: Option<usize>,
+    pub sample: Option<usize>,
+    pub seed: Option<u64>,
+    pub alphabet: Alphabet,
+    pub rna: bool,
+    pub strict: bool,
+    pub complement_table: HashMap<u8, u8>,
+    pub window: Option<usize>,
+    pub step: Option<usize>,
+    pub keep_partial: bool,
+    pub fai: Option<String>,
+    pub fai_out: Option<String>,
+    pub extra_fasta: Vec<String>,
+    pub filter: Option<Filter>,
+    pub chr_alias: HashMap<String, Vec<String>>,
+    pub contig_naming: Option<ContigNaming>,
+    pub contig_md5: HashMap<String, String>,
+    pub keep_description: bool,
+    pub name_format: Option<HeaderTemplate>,
+}
 
 // The Sequences struct contains
-// - the order in which sequences should be printed
-// - the regions as parsed
-// - the FASTA file reader
-// - a list of regions and whether the region is reverse complemented
-// - the file stem of the regions file
+// - the extracted records, in the order in which they should be printed,
+//   with their regions and strands
+// - the FASTA file and its reader, once regions are extracted
+// - a list of regions, whether the region is reverse complemented, and an
+//   optional name for the extracted record
+// - the blocks of regions read from BED12 lines, which are spliced
+// - the file stem of the (first) regions file
+// - the number of regions requested, for --stats
+// - the number of Ns trimmed from the ends of records, if they were trimmed
+// - the number of records spanning assembly gaps, if they were checked
+// - the MD5 digests of contigs, as they're computed
+// - the warnings and notes from reading, extracting, and writing, until
+//   they're taken
 pub struct Sequences {
-    pub order: Vec<String>,
-    pub data: HashMap<String, Record>,
-    reader: IndexedReader<Box<dyn BufReadSeek>>,
-    regions: Vec<(Region, bool)>,
+    records: Vec<Extracted>,
+    fasta_file: String,
+    reader: Option<IndexedReader<Box<dyn BufReadSeek>>>,
+    regions: Vec<(Region, bool, Option<String>)>,
+    blocks: Blocks,
     regions_filename: String,
+    requested: usize,
+    trimmed: Option<usize>,
+    gap_spanning: Option<usize>,
+    digests: HashMap<String, String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+// A summary of an extraction, as reported by --stats.
+pub struct Stats {
+    pub requested: usize,
+    pub extracted: usize,
+    pub reversed: usize,
+    pub bases: usize,
+    pub gc_content: f64,
+    pub trimmed: Option<usize>,
+    pub gap_spanning: Option<usize>,
 }
 
 impl Sequences {
-    // Creating a Sequences struct initializes a blank Vec and HashMap for
-    // the order and data respectively. It initializes the reader and
-    // parses the regions file.
-    pub fn new(fasta_file: &str, region_file: &str) -> Result<Self> {
+    // Creating a Sequences struct initializes a blank Vec for the
+    // extracted records. It initializes the reader and
+    // parses the regions files.
+    pub fn new(
+        fasta_file: &str,
+        region_files: &[String],
+        region_format: Option<RegionFormat>,
+        coords: Coordinates,
+        strict: bool,
+    ) -> Result<Self> {
+        if region_files.iter().filter(|file| *file == "-").count() > 1 {
+            bail!("only one regions file can be read from stdin");
+        }
+        if fasta_file == "-" && region_files.iter().any(|file| file == "-") {
+            bail!("the FASTA file and regions file cannot both be read from stdin");
+        }
+
+        let mut diagnostics = Vec::new();
+        let (regions, blocks) = Self::read_regions_and_blocks(
+            region_files,
+            region_format,
+            coords,
+            strict,
+            &mut diagnostics,
+        )?;
+        let mut sequences = Self::from_regions(fasta_file, regions)?;
+        sequences.blocks = blocks;
+        sequences.diagnostics = diagnostics;
+
+        // The merged contig is named after the first regions file, if any.
+        if let Some(region_file) = region_files.first() {
+            sequences.regions_filename = if region_file == "-" {
+                String::from("stdin")
+            } else {
+                Path::new(&region_file)
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .with_context(|| format!("could not get a contig name from {region_file}"))?
+                    .to_string()
+            };
+        }
+        Ok(sequences)
+    }
+
+    // Creating a Sequences struct from already parsed regions doesn't open
+    // the FASTA file; it's opened when the regions are extracted. A merged
+    // contig is named "merged" unless a contig name is given when writing.
+    pub fn from_regions(
+        fasta_file: &str,
+        regions: Vec<(Region, bool, Option<String>)>,
+    ) -> Result<Self> {
         Ok(Self {
-            order: Vec::new(),
-            data: HashMap::new(),
-            reader: Self::get_reader(fasta_file)?,
-            regions: Self::get_regions(region_file)?,
-            regions_filename: Path::new(&region_file)
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .expect("could not get str")
-                .to_string(),
+            records: Vec::new(),
+            fasta_file: fasta_file.to_string(),
+            reader: None,
+            regions,
+            blocks: HashMap::new(),
+            regions_filename: String::from("merged"),
+            requested: 0,
+            trimmed: None,
+            gap_spanning: None,
+            digests: HashMap::new(),
+            diagnostics: Vec::new(),
         })
     }
 
-    // Extracting the regions in a Sequence struct iterates of the regions
-    // data and reverse complements the extracted record if necessary.
-    // The order and record are stored.
-    pub fn extract(&mut self) -> Result<()> {
-        for (region, reversed) in &self.regions {
-            let mut record = self.reader.query(region)?;
-            if *reversed {
-                let definition = fasta::record::Definition::new(record.name(), None);
-                let sequence: Sequence = record
-                    .sequence()
-                    .complement()
-                    .rev()
-                    .collect::<Result<_, _>>()?;
-                record = fasta::Record::new(definition, sequence);
+    // Add regions to extract after those already given.
+    pub fn add_regions(&mut self, regions: Vec<(Region, bool, Option<String>)>) {
+        self.regions.extend(regions);
+    }
+
+    // Replace the regions to extract, discarding any extracted records, so
+    // an open FASTA file can be queried again (e.g., by a server).
+    #[cfg(feature = "serve")]
+    pub(crate) fn set_regions(&mut self, regions: Vec<(Region, bool, Option<String>)>) {
+        self.regions = regions;
+        self.records.clear();
+    }
+
+    // Return the extracted records in order.
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.records.iter().map(|(_, _, record)| record)
+    }
+
+    // Consume the Sequences struct, returning the extracted records in order.
+    pub fn into_records(self) -> Vec<Record> {
+        self.records
+            .into_iter()
+            .map(|(_, _, record)| record)
+            .collect()
+    }
+
+    // Take the warnings and notes reported so far, in the order they were
+    // reported.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    // Extracting the regions in a Sequence struct checks that their contigs
+    // exist, then extracts each region, optionally across multiple threads.
+    // Each record is stored in the order its region was requested, even if
+    // names repeat.
+    pub fn extract(&mut self, options: ExtractOptions) -> Result<()> {
+        self.extract_or_prepare(&options, true)
+    }
+
+    // Count the bases that extracting the regions would give for each contig,
+    // in the order contigs first appear in the regions. Each region's final
+    // length is taken from its coordinates and the index without reading
+    // the FASTA file, unless translating, padding, or applying variants
+    // (which can change lengths) or filtering (which needs the sequences),
+    // when the regions are extracted and the records that pass any filter
+    // are counted.
+    pub fn count_bases(&mut self, options: ExtractOptions) -> Result<Vec<(String, usize)>> {
+        let query = options.translate
+            || options.pad_to_length.is_some()
+            || !options.consensus.is_empty()
+            || options.filter.is_some();
+        self.extract_or_prepare(&options, query)?;
+
+        let lengths: Vec<(&str, usize)> = if query {
+            self.records
+                .iter()
+                .map(|(region, _, record)| (region.name(), record.sequence().len()))
+                .collect()
+        } else {
+            let contigs: HashMap<&str, u64> = self
+                .reader
+                .iter()
+                .flat_map(|reader| reader.index())
+                .map(|record| (record.name(), record.length()))
+                .collect();
+            self.regions
+                .iter()
+                .map(|(region, _, name)| {
+                    let length = match self.blocks.get(&Self::block_key(region, name)) {
+                        Some(blocks) => blocks
+                            .iter()
+                            .map(|block| Self::region_length(block, contigs[block.name()]))
+                            .sum(),
+                        None => {
+                            // Queries stop at the end of the contig.
+                            let contig_length = contigs[region.name()] as usize;
+                            let interval = region.interval();
+                            let start = interval.start().map_or(1, usize::from);
+                            let end = interval.end().map_or(contig_length, usize::from);
+                            if Self::wraps(region).is_some() {
+                                (contig_length + 1).saturating_sub(start) + end.min(contig_length)
+                            } else {
+                                (end.min(contig_length) + 1).saturating_sub(start)
+                            }
+                        }
+                    };
+                    (region.name(), length)
+                })
+                .collect()
+        };
+
+        let mut counts: Vec<(String, usize)> = Vec::new();
+        let mut positions: HashMap<&str, usize> = HashMap::new();
+        for (contig, length) in lengths {
+            let position = *positions.entry(contig).or_insert_with(|| {
+                counts.push((contig.to_string(), 0));
+                counts.len() - 1
+            });
+            counts[position].1 += length;
+        }
+        Ok(counts)
+    }
+
+    // Extract the regions after resolving, checking, and adjusting them, or
+    // only resolve, check, and adjust them, without querying the FASTA file.
+    fn extract_or_prepare(&mut self, options: &ExtractOptions, query: bool) -> Result<()> {
+        Self::check_options(options)?;
+        if let Some(size) = options.sample {
+            Self::sample_regions(&mut self.regions, size, options.seed);
+        }
+        Self::resolve_names(&mut self.regions, &options.name_map);
+        Self::resolve_digests(&mut self.regions, &mut self.blocks, &options.contig_md5);
+        self.open_reader(options)?;
+
+        let lengths: Vec<(String, u64)> = self
+            .reader
+            .iter()
+            .flat_map(|reader| reader.index())
+            .map(|record| (record.name().to_string(), record.length()))
+            .collect();
+        let names: Vec<&str> = lengths.iter().map(|(name, _)| name.as_str()).collect();
+        let contigs: HashMap<&str, u64> = lengths
+            .iter()
+            .map(|(name, length)| (name.as_str(), *length))
+            .collect();
+        self.resolve_regions(options, &names, &contigs)?;
+        self.adjust_regions(options, &contigs)?;
+
+        if !query {
+            self.records.clear();
+            return Ok(());
+        }
+        self.query_regions(options)?;
+        self.filter_records(options);
+        Ok(())
+    }
+
+    // Check for options that can't be used together.
+    fn check_options(options: &ExtractOptions) -> Result<()> {
+        if options.alphabet == Alphabet::Protein && options.translate {
+            bail!("protein sequences can't be translated");
+        }
+        if options.alphabet == Alphabet::Protein && options.rna {
+            bail!("protein sequences can't be converted to RNA");
+        }
+        if options.revcomp_all && options.no_revcomp {
+            bail!("regions can't all be reverse complemented and all kept on the forward strand");
+        }
+        Ok(())
+    }
+
+    // Resolve the regions' contigs against the index (aliases, patterns,
+    // and coordinates relative to the ends of contigs), then check that
+    // every region names a contig in the index before querying anything, so
+    // all missing contigs are reported at once.
+    fn resolve_regions(
+        &mut self,
+        options: &ExtractOptions,
+        names: &[&str],
+        contigs: &HashMap<&str, u64>,
+    ) -> Result<()> {
+        Self::alias_contigs(&mut self.regions, &mut self.blocks, options, contigs);
+        Self::expand_patterns(&mut self.regions, names, contigs, &mut self.diagnostics)?;
+        Self::resolve_relative(&mut self.regions, contigs)?;
+        let mut missing: Vec<&str> = Vec::new();
+        for (region, _, _) in &self.regions {
+            if !contigs.contains_key(region.name()) && !missing.contains(&region.name()) {
+                missing.push(region.name());
+            }
+        }
+        if !missing.is_empty() && !options.name_map.is_empty() {
+            bail!(
+                "names not found in the name map or FASTA index: {}",
+                missing.join(", ")
+            );
+        } else if !missing.is_empty() {
+            bail!(
+                "contigs not found in the FASTA index: {}",
+                missing.join(", ")
+            );
+        }
+
+        // A region that starts after it ends wraps around the origin of a
+        // circular contig (e.g., a plasmid), if contigs are circular.
+        if !options.circular {
+            for (region, _, _) in &self.regions {
+                if Self::wraps(region).is_some() {
+                    bail!("region {region} starts after it ends (use --circular for regions that wrap around a contig)");
+                }
             }
-            let record_name = record.name().to_string();
-            self.order.push(record_name.clone());
-            self.data.insert(record_name, record);
         }
         Ok(())
     }
 
-    // Writing output from a Sequences struct checks
-    // - whether the output location is a file or stdout
-    // - whether all contigs or a single merged contig should be written
-    // - what the name of the single merged contig should be
-    // - whether the single merged contig should have gaps of a specific size
-    pub fn write(
-        &self,
-        output_location: Option<String>,
-        merge: bool,
-        contig_name: Option<String>,
-        gap_size: usize,
+    // Adjust the resolved regions as requested (clamping, flanking, setting
+    // strands, excluding, merging, and tiling), then check their final
+    // lengths.
+    fn adjust_regions(
+        &mut self,
+        options: &ExtractOptions,
+        contigs: &HashMap<&str, u64>,
     ) -> Result<()> {
-        // Get a Writer to stdout or a file.
-        let mut writer: fasta::Writer<Box<dyn Write>> = match output_location {
-            Some(path) => fasta::Writer::new(Box::new(File::create(path)?)),
-            None => fasta::Writer::new(Box::new(io::stdout().lock())),
+        // The regions requested are counted before excluding, merging, or
+        // tiling changes how many there are.
+        self.requested = self.regions.len();
+
+        // Clamp regions that extend past the end of their contig, so they
+        // can still be queried (and annotated with the adjusted region).
+        if options.clamp {
+            for (region, _, name) in &mut self.regions {
+                let clamped = Self::clamp_region(region, contigs[region.name()])?;
+                if clamped != *region {
+                    if self.blocks.contains_key(&Self::block_key(region, name)) {
+                        bail!("can't clamp region {region}, which has BED12 blocks");
+                    }
+                    self.diagnostics.push(Diagnostic::Warning(format!(
+                        "clamped region {region} to {clamped}"
+                    )));
+                    *region = clamped;
+                }
+            }
+        }
+
+        // The blocks of BED12 regions are looked up by their regions, so
+        // regions with blocks can't be changed into other regions.
+        if !self.blocks.is_empty()
+            && (options.flank_left > 0
+                || options.flank_right > 0
+                || !options.exclude.is_empty()
+                || options.merge_overlaps
+                || options.window.is_some())
+        {
+            bail!("regions with BED12 blocks can't be flanked, excluded, merged, or tiled into windows");
+        }
+
+        // Extend regions by the left and right flanks on genomic coordinates
+        // (before any reverse complementing), so left is always upstream on
+        // the forward strand.
+        let (left, right) = (options.flank_left, options.flank_right);
+        if left > 0 || right > 0 {
+            for (region, _, _) in &mut self.regions {
+                let length = contigs[region.name()];
+                *region = Self::flank_region(region, left, right, length)?;
+            }
+        }
+
+        // Mark every region for reverse complementing, or none of them, if
+        // requested.
+        if options.revcomp_all || options.no_revcomp {
+            for (_, reversed, _) in &mut self.regions {
+                *reversed = options.revcomp_all;
+            }
+        }
+
+        // Remove excluded spans from regions, which may split them.
+        if !options.exclude.is_empty() {
+            let regions = std::mem::take(&mut self.regions);
+            self.regions =
+                Self::exclude(regions, &options.exclude, contigs, &mut self.diagnostics)?;
+        }
+
+        // Protein sequences can only be reversed, not complemented.
+        if options.alphabet == Alphabet::Protein && !options.reverse_only {
+            if let Some((region, _, _)) = self.regions.iter().find(|(_, reversed, _)| *reversed) {
+                bail!(
+                    "can't reverse complement {region}: protein sequences have no complement (use --reverse-only to reverse them)"
+                );
+            }
+        }
+
+        // Merge overlapping and adjacent regions, which reorders them.
+        if options.merge_overlaps {
+            let regions = std::mem::take(&mut self.regions);
+            self.regions = Self::merge_overlaps(regions, contigs)?;
+        }
+
+        // Tile regions into windows, which replace them.
+        if let Some(size) = options.window {
+            let step = options.step.unwrap_or(size);
+            if size == 0 || step == 0 {
+                bail!("window size and step must be at least 1");
+            }
+            let regions = std::mem::take(&mut self.regions);
+            self.regions =
+                Self::window_regions(regions, size, step, options.keep_partial, contigs)?;
+        }
+
+        // Check region lengths once they're final, with unbounded regions
+        // extending to the ends of their contig.
+        if let Some(max_length) = options.max_region_length {
+            for (region, _, _) in &self.regions {
+                let length = Self::region_length(region, contigs[region.name()]);
+                if length > max_length {
+                    bail!("region {region} is {length} bases, longer than the maximum of {max_length}");
+                }
+            }
+        }
+
+        // Skip regions that are too short, noting each one.
+        if let Some(min_length) = options.min_length {
+            let diagnostics = &mut self.diagnostics;
+            self.regions.retain(|(region, _, _)| {
+                let length = Self::region_length(region, contigs[region.name()]);
+                if length < min_length {
+                    diagnostics.push(Diagnostic::Note(format!(
+                        "skipping region {region} ({length} bases, shorter than {min_length})"
+                    )));
+                }
+                length >= min_length
+            });
+        }
+        Ok(())
+    }
+
+    // Query the regions from the open FASTA file, reporting progress after
+    // each region if requested. Regions are extracted by cluster, reading
+    // the span of each cluster of overlapping regions once, then put back
+    // in the order given.
+    fn query_regions(&mut self, options: &ExtractOptions) -> Result<()> {
+        let Some(reader) = self.reader.as_mut() else {
+            bail!("{} is not open", self.fasta_file);
+        };
+        let total = self.regions.len();
+        let done = AtomicUsize::new(0);
+        if let Some(progress) = &options.progress {
+            progress(0, total);
+        }
+        let clusters = Self::clusters(&self.regions);
+        let regions = &self.regions;
+        let blocks = &self.blocks;
+        let extract_cluster = |reader: &mut IndexedReader<Box<dyn BufReadSeek>>,
+                               (span, indices): &(Option<Region>, Vec<usize>)|
+         -> Result<ExtractedCluster> {
+            let mut diagnostics = Vec::new();
+            let span = match span {
+                Some(span) => {
+                    let start = span.interval().start().map_or(1, usize::from);
+                    let bases = reader.query(span)?.sequence().as_ref().to_vec();
+                    Some((span.name().to_string(), start, bases))
+                }
+                None => None,
+            };
+            let records = indices
+                .iter()
+                .map(|&i| {
+                    let (region, reversed, name) = &regions[i];
+                    let region_blocks = blocks.get(&Self::block_key(region, name));
+                    let record = Self::extract_region(
+                        reader,
+                        span.as_ref(),
+                        &regions[i],
+                        region_blocks.map(Vec::as_slice),
+                        options,
+                        &mut diagnostics,
+                    )?;
+                    if let Some(progress) = &options.progress {
+                        progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+                    }
+                    Ok((i, (region.clone(), *reversed, record)))
+                })
+                .collect::<Result<_>>()?;
+            Ok((records, diagnostics))
         };
 
-        if !merge {
-            // If the user didn't request a merged contig, write each contig.
-            for key in &self.order {
-                let record = &self.data.get(key).expect("could not get key");
-                writer.write_record(record)?;
+        // IndexedReader isn't Sync, so each thread opens its own reader from
+        // the same path and index (or the same files, when several are
+        // joined). A FASTA read from stdin or a URL only exists in the main
+        // reader and is always extracted sequentially.
+        let mut extracted = Vec::with_capacity(clusters.len());
+        if options.threads > 1
+            && self.fasta_file != "-"
+            && !is_url(&self.fasta_file)
+            && !options.no_index
+        {
+            let index = reader.index();
+            let fasta_file = &self.fasta_file;
+            let pool = ThreadPoolBuilder::new()
+                .num_threads(options.threads)
+                .build()?;
+            extracted = pool.install(|| {
+                clusters
+                    .par_iter()
+                    .map_init(
+                        || {
+                            if options.extra_fasta.is_empty() {
+                                fasta::indexed_reader::Builder::default()
+                                    .set_index(index::clone_index(index))
+                                    .build_from_path(fasta_file)
+                                    .map_err(anyhow::Error::from)
+                            } else {
+                                // The indexes were checked (and any problems
+                                // reported) when the main reader was opened.
+                                Self::open_indexed(fasta_file, options, &mut Vec::new())
+                            }
+                        },
+                        |reader, cluster| match reader {
+                            Ok(reader) => extract_cluster(reader, cluster),
+                            Err(e) => bail!("could not open {fasta_file}: {e}"),
+                        },
+                    )
+                    .collect::<Result<Vec<_>>>()
+            })?;
+        } else {
+            for cluster in &clusters {
+                extracted.push(extract_cluster(reader, cluster)?);
+            }
+        }
+        let mut records: Vec<(usize, Extracted)> = Vec::with_capacity(regions.len());
+        for (cluster, diagnostics) in extracted {
+            records.extend(cluster);
+            self.diagnostics.extend(diagnostics);
+        }
+        records.sort_by_key(|(i, _)| *i);
+        self.records = records.into_iter().map(|(_, record)| record).collect();
+        Ok(())
+    }
+
+    // Drop records that don't pass the filter, if there is one.
+    fn filter_records(&mut self, options: &ExtractOptions) {
+        if let Some(filter) = &options.filter {
+            let extracted = self.records.len();
+            self.records
+                .retain(|(_, _, record)| filter.matches(record.sequence().as_ref()));
+            let dropped = extracted - self.records.len();
+            if dropped > 0 {
+                self.diagnostics.push(Diagnostic::Note(format!(
+                    "skipping {dropped} records that don't pass the filter"
+                )));
             }
+        }
+    }
+
+    // Summarize the extraction: how many regions were requested, extracted,
+    // and reverse complemented, the number of extracted bases and their GC
+    // content, and how many Ns were trimmed and records spanned gaps when
+    // writing, if they were.
+    pub fn stats(&self) -> Stats {
+        let (mut bases, mut gc) = (0, 0);
+        for (_, _, record) in &self.records {
+            let sequence = record.sequence().as_ref();
+            bases += sequence.len();
+            gc += sequence
+                .iter()
+                .filter(|base| matches!(base.to_ascii_uppercase(), b'G' | b'C'))
+                .count();
+        }
+        let gc_content = if bases > 0 {
+            100.0 * gc as f64 / bases as f64
+        } else {
+            0.0
+        };
+        let reversed = self
+            .records
+            .iter()
+            .filter(|(_, reversed, _)| *reversed)
+            .count();
+        Stats {
+            requested: self.requested,
+            extracted: self.records.len(),
+            reversed,
+            bases,
+            gc_content,
+            trimmed: self.trimmed,
+            gap_spanning: self.gap_spanning,
+        }
+    }
+
+    // Return the length of a region, with unbounded ends extending to the
+    // ends of its contig.
+    // Regions that wrap around a circular contig include both ends of it.
+    fn region_length(region: &Region, contig_length: u64) -> usize {
+        let interval = region.interval();
+        let start = interval.start().map(usize::from).unwrap_or(1);
+        let end = interval
+            .end()
+            .map(usize::from)
+            .unwrap_or(contig_length as usize);
+        if Self::wraps(region).is_some() {
+            (contig_length as usize + 1).saturating_sub(start) + end
         } else {
-            // Create a gap if the user specified a gap size.
-            let gap = if gap_size > 0 {
-                Some("N".repeat(gap_size))
+            (end + 1).saturating_sub(start)
+        }
+    }
+
+    // Subtract excluded regions from regions, like bedtools subtract: each
+    // region is split into the pieces not covered by any excluded region on
+    // its contig, ignoring the excluded regions' strands. Unbounded ends
+    // extend to the end of the contig, and regions wrapping around a
+    // circular contig are split at its origin unless a piece spans it.
+    // Pieces keep their region's strand and name, and are in reverse order
+    // for a reverse complemented region, so merging them gives the reverse
+    // complement of what's left. Regions that don't overlap an excluded
+    // region are unchanged, and regions excluded entirely are skipped with
+    // a warning.
+    fn exclude(
+        regions: Vec<(Region, bool, Option<String>)>,
+        exclude: &[Region],
+        contigs: &HashMap<&str, u64>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        // The 1-based, inclusive intervals a region covers: two for a region
+        // wrapping around the origin.
+        let intervals = |region: &Region| {
+            let length = contigs
+                .get(region.name())
+                .map_or(0, |&length| length as usize);
+            let interval = region.interval();
+            let start = interval.start().map_or(1, usize::from);
+            let end = interval.end().map_or(length, usize::from);
+            if start > end {
+                vec![(start, length), (1, end)]
+            } else {
+                vec![(start, end)]
+            }
+        };
+        let mut excluded: HashMap<&str, Vec<(usize, usize)>> = HashMap::new();
+        for region in exclude {
+            excluded
+                .entry(region.name())
+                .or_default()
+                .extend(intervals(region));
+        }
+        for intervals in excluded.values_mut() {
+            intervals.sort_unstable();
+        }
+
+        let mut kept = Vec::with_capacity(regions.len());
+        for (region, reversed, name) in regions {
+            let Some(excluded) = excluded.get(region.name()) else {
+                kept.push((region, reversed, name));
+                continue;
+            };
+            let mut pieces: Vec<(usize, usize)> = Vec::new();
+            let mut overlapped = false;
+            let mut origin = 0;
+            for (start, end) in intervals(&region) {
+                origin = pieces.len();
+                let mut next = start;
+                for &(excluded_start, excluded_end) in excluded {
+                    if excluded_end < next || excluded_start > end {
+                        continue;
+                    }
+                    overlapped = true;
+                    if excluded_start > next {
+                        pieces.push((next, excluded_start - 1));
+                    }
+                    next = next.max(excluded_end + 1);
+                }
+                if next <= end {
+                    pieces.push((next, end));
+                }
+            }
+            if !overlapped {
+                kept.push((region, reversed, name));
+                continue;
+            }
+            if pieces.is_empty() {
+                diagnostics.push(Diagnostic::Warning(format!(
+                    "skipping region {region}, which is excluded entirely"
+                )));
+                continue;
+            }
+
+            // Rejoin the pieces on either side of the origin of a wrapping
+            // region if neither end of the contig was excluded.
+            if Self::wraps(&region).is_some() && origin > 0 && origin < pieces.len() {
+                let length = contigs[region.name()] as usize;
+                if pieces[origin - 1].1 == length && pieces[origin].0 == 1 {
+                    pieces[origin - 1].1 = pieces.remove(origin).1;
+                }
+            }
+            let mut split = pieces
+                .into_iter()
+                .map(|(start, end)| {
+                    let interval = Position::try_from(start)?..=Position::try_from(end)?;
+                    Ok((Region::new(region.name(), interval), reversed, name.clone()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if reversed {
+                split.reverse();
+            }
+            kept.extend(split);
+        }
+        Ok(kept)
+    }
+
+    // Return the start and end of a region that starts after it ends, i.e.,
+    // wraps around the origin of a circular contig.
+    fn wraps(region: &Region) -> Option<(Position, Position)> {
+        let interval = region.interval();
+        match (interval.start(), interval.end()) {
+            (Some(start), Some(end)) if start > end => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    // Clamp a region's end to the length of its contig. A region that starts
+    // past the end of the contig can't be clamped to anything meaningful.
+    fn clamp_region(region: &Region, length: u64) -> Result<Region> {
+        let interval = region.interval();
+        let start = interval.start().unwrap_or(Position::MIN);
+        if usize::from(start) as u64 > length {
+            bail!(
+                "region {region} starts past the end of {} (length {length})",
+                region.name()
+            );
+        }
+        match interval.end() {
+            Some(end) if usize::from(end) as u64 > length => {
+                let end = Position::try_from(length as usize)?;
+                Ok(Region::new(region.name(), start..=end))
+            }
+            _ => Ok(region.clone()),
+        }
+    }
+
+    // Merge regions on the same contig and strand that overlap or are
+    // adjacent (e.g., chr1:1-10 and chr1:11-20 become chr1:1-20), as
+    // `bedtools merge` does. Regions are returned grouped by contig and
+    // strand, in the order each group first appears, and sorted by start
+    // within each group. A region keeps its name only if nothing was merged
+    // into it. Regions that wrap around a circular contig aren't merged and
+    // follow the others in their group.
+    fn merge_overlaps(
+        regions: Vec<(Region, bool, Option<String>)>,
+        contigs: &HashMap<&str, u64>,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        // Each group holds the start, end, and (while unmerged) the
+        // original region and name of each interval, and wrapped regions.
+        type Named = (Region, Option<String>);
+        type Interval = (usize, usize, Option<Named>);
+        let mut keys: HashMap<(String, bool), usize> = HashMap::new();
+        let mut groups: Vec<(String, bool, Vec<Interval>, Vec<Named>)> = Vec::new();
+        for (region, reversed, name) in regions {
+            let group = *keys
+                .entry((region.name().to_string(), reversed))
+                .or_insert_with(|| {
+                    groups.push((region.name().to_string(), reversed, Vec::new(), Vec::new()));
+                    groups.len() - 1
+                });
+            let (_, _, intervals, wrapped) = &mut groups[group];
+            if Self::wraps(&region).is_some() {
+                wrapped.push((region, name));
+                continue;
+            }
+            let interval = region.interval();
+            let start = interval.start().map(usize::from).unwrap_or(1);
+            let end = interval
+                .end()
+                .map(usize::from)
+                .unwrap_or(contigs[region.name()] as usize);
+            intervals.push((start, end, Some((region, name))));
+        }
+
+        let mut merged = Vec::new();
+        for (contig, reversed, mut intervals, wrapped) in groups {
+            intervals.sort_by_key(|(start, end, _)| (*start, *end));
+            let mut group: Vec<Interval> = Vec::new();
+            for interval in intervals {
+                match group.last_mut() {
+                    Some((_, end, original)) if interval.0 <= *end + 1 => {
+                        *end = (*end).max(interval.1);
+                        *original = None;
+                    }
+                    _ => group.push(interval),
+                }
+            }
+            for (start, end, original) in group {
+                match original {
+                    Some((region, name)) => merged.push((region, reversed, name)),
+                    None => {
+                        let interval = Position::try_from(start)?..=Position::try_from(end)?;
+                        merged.push((Region::new(contig.as_str(), interval), reversed, None));
+                    }
+                }
+            }
+            merged.extend(
+                wrapped
+                    .into_iter()
+                    .map(|(region, name)| (region, reversed, name)),
+            );
+        }
+        Ok(merged)
+    }
+
+    // Tile each region into windows of a size, starting every step bases
+    // (overlapping when the step is smaller than the size), named after the
+    // region's output name or region string with the window's number (e.g.,
+    // chr1:100-600_w1). Windows of a reverse complemented region start from
+    // its end, so the first window is the start of the extracted sequence. A
+    // last window shorter than the size (running to the end of the region)
+    // is dropped unless partial windows are kept, and a region shorter than
+    // the size gives no windows unless they are. Unbounded ends extend to
+    // the ends of the contig, and regions wrapping around a circular contig
+    // can't be tiled.
+    fn window_regions(
+        regions: Vec<(Region, bool, Option<String>)>,
+        size: usize,
+        step: usize,
+        keep_partial: bool,
+        contigs: &HashMap<&str, u64>,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        let mut windows = Vec::new();
+        for (region, reversed, name) in regions {
+            if Self::wraps(&region).is_some() {
+                bail!("can't tile region {region}, which wraps around its contig, into windows");
+            }
+            let interval = region.interval();
+            let start = interval.start().map_or(1, usize::from);
+            let end = interval
+                .end()
+                .map_or(contigs[region.name()] as usize, usize::from);
+            let length = (end + 1).saturating_sub(start);
+            let prefix = name.unwrap_or_else(|| region.to_string());
+
+            // The 0-based offsets and lengths of the windows within the
+            // region, in the order of the extracted sequence.
+            let mut offsets = Vec::new();
+            let mut offset = 0;
+            while offset + size <= length {
+                offsets.push((offset, size));
+                offset += step;
+            }
+            let covered = offsets.last().map_or(0, |(offset, size)| offset + size);
+            if keep_partial && covered < length && offset < length {
+                offsets.push((offset, length - offset));
+            }
+
+            for (i, (offset, window)) in offsets.into_iter().enumerate() {
+                let window_start = if reversed {
+                    end + 1 - offset - window
+                } else {
+                    start + offset
+                };
+                let interval = Position::try_from(window_start)?
+                    ..=Position::try_from(window_start + window - 1)?;
+                let name = format!("{prefix}_w{}", i + 1);
+                windows.push((Region::new(region.name(), interval), reversed, Some(name)));
+            }
+        }
+        Ok(windows)
+    }
+
+    // Extend a region by some number of bases to the left and right, without
+    // going past either end of its contig. Unbounded ends are left as is.
+    fn flank_region(region: &Region, left: usize, right: usize, length: u64) -> Result<Region> {
+        let interval = region.interval();
+        let start = match interval.start() {
+            Some(start) => Some(Position::try_from(
+                usize::from(start).saturating_sub(left).max(1),
+            )?),
+            None => None,
+        };
+        let end = match interval.end() {
+            Some(end) => Some(Position::try_from(
+                (usize::from(end) + right).min(length as usize),
+            )?),
+            None => None,
+        };
+        Ok(match (start, end) {
+            (Some(start), Some(end)) => Region::new(region.name(), start..=end),
+            (Some(start), None) => Region::new(region.name(), start..),
+            (None, Some(end)) => Region::new(region.name(), ..=end),
+            (None, None) => region.clone(),
+        })
+    }
+
+    // Group regions into clusters of overlapping or adjacent regions on the
+    // same contig, in contig and start order, with the span covering each
+    // cluster. Each span is read once and its regions sliced from memory,
+    // rather than reading the overlapping bases for every region. Regions
+    // that don't overlap another, are unbounded, or wrap around a circular
+    // contig are clusters of their own, without a span, and spans are limited
+    // to MAX_SPAN bases so dense regions across a whole contig aren't read
+    // into memory at once.
+    fn clusters(regions: &[(Region, bool, Option<String>)]) -> Vec<(Option<Region>, Vec<usize>)> {
+        const MAX_SPAN: usize = 1 << 24;
+
+        let mut clusters = Vec::new();
+        let mut bounded = Vec::new();
+        for (i, (region, _, _)) in regions.iter().enumerate() {
+            let interval = region.interval();
+            match (interval.start(), interval.end()) {
+                (Some(start), Some(end)) if start <= end => {
+                    bounded.push((region.name(), usize::from(start), usize::from(end), i));
+                }
+                _ => clusters.push((None, vec![i])),
+            }
+        }
+        bounded.sort_unstable();
+
+        // The cluster being grown: contig, start, end, and its regions.
+        let mut current: Option<(&str, usize, usize, Vec<usize>)> = None;
+        let mut finish = |(name, start, end, indices): (&str, usize, usize, Vec<usize>)| {
+            let span = if indices.len() > 1 {
+                Position::try_from(start)
+                    .and_then(|start| Ok(Region::new(name, start..=Position::try_from(end)?)))
+                    .ok()
             } else {
                 None
             };
+            clusters.push((span, indices));
+        };
+        for (name, start, end, i) in bounded {
+            match &mut current {
+                Some((current_name, current_start, current_end, indices))
+                    if *current_name == name
+                        && start <= *current_end + 1
+                        && end.max(*current_end) - *current_start < MAX_SPAN =>
+                {
+                    *current_end = end.max(*current_end);
+                    indices.push(i);
+                }
+                _ => {
+                    if let Some(cluster) = current.replace((name, start, end, vec![i])) {
+                        finish(cluster);
+                    }
+                }
+            }
+        }
+        if let Some(cluster) = current {
+            finish(cluster);
+        }
+        clusters
+    }
 
-            // Iterate over the sequence data in order, extracting the sequence data from
-            // the record and converting it to &str. Store the sequence data in a Vec, and
-            // add the gap sequence if it exists. The resulting Vec<&str> is flattened, and
-            // the Vec of sequence data (and optional gaps) is concatenated.
-            let last_sequence = self.order.last().expect("could not get last sequence");
-            let sequences: String = self
-                .order
+    // Query a region (or slice it from the span of its cluster, if it's
+    // within the span), masking bases overlapping any mask regions with N.
+    fn query(
+        reader: &mut IndexedReader<Box<dyn BufReadSeek>>,
+        span: Option<&Span>,
+        region: &Region,
+        options: &ExtractOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Record> {
+        let interval = region.interval();
+        let sliced = match (span, interval.start(), interval.end()) {
+            (Some((name, span_start, bases)), Some(start), Some(end)) if name == region.name() => {
+                let (start, end) = (usize::from(start), usize::from(end));
+                (start >= *span_start && end < span_start + bases.len())
+                    .then(|| &bases[start - span_start..=end - span_start])
+            }
+            _ => None,
+        };
+        let record = match sliced {
+            Some(bases) => {
+                let definition = fasta::record::Definition::new(region.to_string(), None);
+                fasta::Record::new(definition, bases.to_vec().into())
+            }
+            None => reader.query(region)?,
+        };
+        if options.mask.is_empty() && options.consensus.is_empty() {
+            return Ok(record);
+        }
+        let mut sequence = Self::mask(region, record.sequence(), &options.mask, options.alphabet);
+
+        // Variants are applied after masking, since indels shift the bases
+        // that mask regions (in genomic coordinates) would cover.
+        if let Some(variants) = options.consensus.get(region.name()) {
+            let start = region.interval().start().map(usize::from).unwrap_or(1);
+            let bases = consensus::apply(
+                region.name(),
+                start,
+                sequence.as_ref(),
+                variants,
+                diagnostics,
+            )?;
+            sequence = bases.into();
+        }
+        Ok(fasta::Record::new(record.definition().clone(), sequence))
+    }
+
+    // Extracting a region queries the reader (in two pieces, for a region
+    // wrapping around a circular contig, or a piece for each block of a BED12
+    // region, spliced together in genomic order), masks bases overlapping any
+    // mask regions with N, renames the extracted record if the region was
+    // named (or after the region and strand, if requested), and reverse
+    // complements it if necessary (or always, when reverse complementing all
+    // regions), or only reverses it without complementing, if requested.
+    // Soft-masked (lowercase) bases are first replaced with N, if requested,
+    // as they're soft-masked in the reference, so neither the case conversion
+    // nor a complement table that changes case affects which bases are
+    // masked. The sequence is uppercased before reverse complementing (so the
+    // complement table sees uppercase bases) or lowercased after it, if
+    // requested, and is then converted to RNA or translated to amino acids if
+    // requested and padded or truncated to a fixed length if requested. When
+    // annotating, the description is set to the region and strand. The
+    // description of a whole contig's definition line is kept (after any
+    // annotation), if requested.
+    fn extract_region(
+        reader: &mut IndexedReader<Box<dyn BufReadSeek>>,
+        span: Option<&Span>,
+        (region, reversed, name): &(Region, bool, Option<String>),
+        blocks: Option<&[Region]>,
+        options: &ExtractOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Record> {
+        let mut record = if let Some(blocks) = blocks {
+            let mut sequence = Vec::new();
+            for block in blocks {
+                let record = Self::query(reader, span, block, options, diagnostics)?;
+                sequence.extend_from_slice(record.sequence().as_ref());
+            }
+            let definition = fasta::record::Definition::new(region.to_string(), None);
+            fasta::Record::new(definition, sequence.into())
+        } else if let Some((start, end)) = Self::wraps(region) {
+            // Query from the start to the end of the contig, then from the
+            // beginning of the contig to the end of the region.
+            let length = reader
+                .index()
                 .iter()
-                .flat_map(|sequence| {
-                    let record = &self.data.get(sequence).expect("could not get key");
-                    let start = Position::try_from(1).expect("could not get position");
-                    let end = Position::try_from(record.sequence().len())
-                        .expect("could not get position");
-                    let mut sequence_data = vec![str::from_utf8(
-                        record
-                            .sequence()
-                            .get(start..=end)
-                            .expect("could not get sequence"),
-                    )
-                    .expect("could not convert sequence to String")];
-                    if sequence != last_sequence {
-                        if let Some(gap) = &gap {
-                            sequence_data.push(gap);
-                        }
+                .find(|record| record.name() == region.name())
+                .map_or(0, |record| record.length() as usize);
+            let first = Region::new(region.name(), start..=Position::try_from(length)?);
+            let second = Region::new(region.name(), Position::MIN..=end);
+            let mut sequence = Self::query(reader, span, &first, options, diagnostics)?
+                .sequence()
+                .as_ref()
+                .to_vec();
+            sequence.extend_from_slice(
+                Self::query(reader, span, &second, options, diagnostics)?
+                    .sequence()
+                    .as_ref(),
+            );
+            let definition = fasta::record::Definition::new(region.to_string(), None);
+            fasta::Record::new(definition, sequence.into())
+        } else {
+            Self::query(reader, span, region, options, diagnostics)?
+        };
+        let strand = if *reversed { '-' } else { '+' };
+        let name = if options.region_names {
+            Some(format!("{region}({strand})"))
+        } else if let Some(format) = &options.name_format {
+            let name = name.as_deref().unwrap_or(region.name());
+            Some(format.render(name, region, strand, record.sequence().len()))
+        } else {
+            name.clone()
+        };
+        if let Some(name) = name {
+            let definition = fasta::record::Definition::new(name, None);
+            record = fasta::Record::new(definition, record.sequence().clone());
+        }
+        if options.hard_mask {
+            let sequence: Vec<u8> = record
+                .sequence()
+                .as_ref()
+                .iter()
+                .map(|&base| {
+                    if base.is_ascii_lowercase() {
+                        options.alphabet.unknown()
+                    } else {
+                        base
                     }
-                    sequence_data
                 })
-                .collect::<Vec<&str>>()
-                .join("");
-
-            // Select the contig name from either user input or the regions file's name.
-            let contig_name = if let Some(contig_name) = contig_name {
-                contig_name
+                .collect();
+            record = fasta::Record::new(record.definition().clone(), sequence.into());
+        }
+        if let Some(Case::Upper) = options.case {
+            let sequence = record.sequence().as_ref().to_ascii_uppercase();
+            record = fasta::Record::new(record.definition().clone(), sequence.into());
+        }
+        if *reversed {
+            let definition = fasta::record::Definition::new(record.name(), None);
+            let sequence = if options.reverse_only {
+                record.sequence().as_ref().iter().rev().copied().collect()
+            } else {
+                Self::reverse_complement(
+                    record.sequence(),
+                    options.alphabet,
+                    &options.complement_table,
+                )?
+            };
+            record = fasta::Record::new(definition, sequence);
+        }
+        if let Some(Case::Lower) = options.case {
+            let sequence = record.sequence().as_ref().to_ascii_lowercase();
+            record = fasta::Record::new(record.definition().clone(), sequence.into());
+        }
+        if options.rna {
+            let sequence: Vec<u8> = record
+                .sequence()
+                .as_ref()
+                .iter()
+                .map(|&base| match base {
+                    b'T' => b'U',
+                    b't' => b'u',
+                    _ => base,
+                })
+                .collect();
+            record = fasta::Record::new(record.definition().clone(), sequence.into());
+        }
+        if options.translate {
+            let sequence = translate::translate(record.sequence(), options.frame);
+            record = fasta::Record::new(record.definition().clone(), sequence);
+        }
+        if let Some(length) = options.pad_to_length {
+            // Sequences are padded at their 3' end with N (X for amino
+            // acids, translated or not), or truncated there.
+            let mut sequence = record.sequence().as_ref().to_vec();
+            if sequence.len() > length {
+                diagnostics.push(Diagnostic::Warning(format!(
+                    "truncating {} from {} to {length}",
+                    record.name(),
+                    sequence.len()
+                )));
+            }
+            let padding = if options.translate {
+                b'X'
             } else {
-                self.regions_filename.clone()
+                options.alphabet.unknown()
             };
+            sequence.resize(length, padding);
+            record = fasta::Record::new(record.definition().clone(), sequence.into());
+        }
+        let whole_contig = region.interval().start().is_none() && region.interval().end().is_none();
+        let kept = if options.keep_description && whole_contig && blocks.is_none() {
+            Self::contig_description(reader, region.name())?
+        } else {
+            None
+        };
+        let annotation = options
+            .annotate
+            .then(|| format!("{region} strand={strand}"));
+        let description = match (annotation, kept) {
+            (Some(annotation), Some(kept)) => Some(format!("{annotation} {kept}")),
+            (annotation, kept) => annotation.or(kept),
+        };
+        if description.is_some() {
+            let definition = fasta::record::Definition::new(record.name(), description);
+            record = fasta::Record::new(definition, record.sequence().clone());
+        }
+        Ok(record)
+    }
 
-            // Create and write the record.
-            let definition = fasta::record::Definition::new(contig_name, None);
-            let record = fasta::Record::new(definition, sequences.as_bytes().to_vec().into());
-            writer.write_record(&record)?;
+    // Read the description of a contig from its definition line, which ends
+    // just before the contig's sequence (at its offset in the index), by
+    // reading back from there to the start of the line.
+    fn contig_description(
+        reader: &mut IndexedReader<Box<dyn BufReadSeek>>,
+        name: &str,
+    ) -> Result<Option<String>> {
+        let Some(offset) = reader
+            .index()
+            .iter()
+            .find(|record| record.name() == name)
+            .map(|record| record.offset())
+        else {
+            return Ok(None);
+        };
+        let mut size = 256;
+        loop {
+            let start = offset.saturating_sub(size);
+            let inner = reader.get_mut();
+            inner.seek(SeekFrom::Start(start))?;
+            let mut buf = vec![0; (offset - start) as usize];
+            inner.read_exact(&mut buf)?;
+            let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = match line.iter().rposition(|&byte| byte == b'\n') {
+                Some(i) => &line[i + 1..],
+                None if start > 0 => {
+                    size *= 4;
+                    continue;
+                }
+                None => line,
+            };
+            let definition: fasta::record::Definition = str::from_utf8(line)?
+                .parse()
+                .with_context(|| format!("could not read the definition line of {name}"))?;
+            return Ok(definition.description().map(String::from));
         }
-        Ok(())
     }
 
-    // Return an IndexedReader, creating an index if one does not exist.
-    fn get_reader(fasta_file: &str) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
-        Ok(
-            if std::path::Path::new(&format! {"{fasta_file}.fai"}).exists() {
-                fasta::indexed_reader::Builder::default().build_from_path(fasta_file)?
+    // Replace the bases of a region's sequence that overlap any of the mask
+    // regions with N (X for protein). Both are in genomic coordinates, with
+    // unbounded ends extending to the ends of the contig; a mask region that
+    // wraps around a circular contig masks both ends of it.
+    fn mask(region: &Region, sequence: &Sequence, mask: &[Region], alphabet: Alphabet) -> Sequence {
+        let mut bases = sequence.as_ref().to_vec();
+        let start = region.interval().start().map(usize::from).unwrap_or(1);
+        let end = start + bases.len();
+        for mask_region in mask.iter().filter(|mask| mask.name() == region.name()) {
+            let interval = mask_region.interval();
+            let mask_start = interval.start().map(usize::from).unwrap_or(1);
+            let mask_end = interval
+                .end()
+                .map(|end| usize::from(end) + 1)
+                .unwrap_or(end);
+            let intervals = if Self::wraps(mask_region).is_some() {
+                vec![(mask_start, end), (1, mask_end)]
             } else {
-                let file = File::create(format! {"{fasta_file}.fai"})?;
-                let index = fasta::index(fasta_file)?;
-                let mut writer = fai::Writer::new(file);
-                writer.write_index(&index)?;
-                fasta::indexed_reader::Builder::default()
-                    .set_index(index)
-                    .build_from_path(fasta_file)?
-            },
-        )
+                vec![(mask_start, mask_end)]
+            };
+            for (mask_start, mask_end) in intervals {
+                let (mask_start, mask_end) = (mask_start.max(start), mask_end.min(end));
+                if mask_start < mask_end {
+                    bases[mask_start - start..mask_end - start].fill(alphabet.unknown());
+                }
+            }
+        }
+        bases.into()
+    }
+
+    // Reverse complement a sequence, preserving soft-masking. The noodles
+    // complement table only covers uppercase bases, so the sequence is
+    // complemented in uppercase and lowercase bases are restored afterwards
+    // (e.g., acgT becomes Acgt). The table covers the IUPAC ambiguity codes
+    // (R and Y, K and M, B and V, D and H swap; S, W, and N are their own
+    // complements). RNA is complemented with U rather than T.
+    // Bases in a custom complement table are complemented from it as given,
+    // case and all, instead.
+    fn reverse_complement(
+        sequence: &Sequence,
+        alphabet: Alphabet,
+        table: &HashMap<u8, u8>,
+    ) -> Result<Sequence> {
+        let bases = sequence.as_ref();
+        let uppercase: Vec<u8> = bases
+            .iter()
+            .map(|base| {
+                // The noodles table would reject bases only the custom
+                // table knows, so they're complemented as N and replaced.
+                if table.contains_key(base) {
+                    b'N'
+                } else {
+                    base.to_ascii_uppercase()
+                }
+            })
+            .collect();
+        let uppercase = Sequence::from(uppercase);
+        let mut complement: Vec<u8> = uppercase.complement().rev().collect::<Result<_, _>>()?;
+        for (base, original) in complement.iter_mut().zip(bases.iter().rev()) {
+            if let Some(&custom) = table.get(original) {
+                *base = custom;
+                continue;
+            }
+            if alphabet == Alphabet::Rna && *base == b'T' {
+                *base = b'U';
+            }
+            if original.is_ascii_lowercase() {
+                base.make_ascii_lowercase();
+            }
+        }
+        Ok(complement.into())
     }
 
-    // Parse each non-blank line in the regions file, noting whether
-    // it should be reverse complemented.
-    fn get_regions(region_file: &str) -> Result<Vec<(Region, bool)>> {
-        Ok(read_to_string(region_file)
-            .unwrap()
-            .lines()
-            .filter_map(|region| {
-                if region.is_empty() {
-                    None
+    // Check regions against the contigs in a FASTA file's index without
+    // extracting them, returning each region (with patterns and relative
+    // coordinates resolved) and its status: "ok", or the problem with it.
+    pub fn check_regions(
+        fasta_file: &str,
+        regions: Vec<(Region, bool, Option<String>)>,
+        circular: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<(String, String)>> {
+        let lengths = Self::contig_lengths(fasta_file, diagnostics)?;
+        let names: Vec<&str> = lengths.iter().map(|(name, _)| name.as_str()).collect();
+        let contigs: HashMap<&str, u64> = lengths
+            .iter()
+            .map(|(name, length)| (name.as_str(), *length))
+            .collect();
+
+        let mut statuses = Vec::new();
+        for region in regions {
+            let written = region.0.to_string();
+            let mut resolved = vec![region];
+            if let Err(e) = Self::expand_patterns(&mut resolved, &names, &contigs, diagnostics)
+                .and_then(|()| Self::resolve_relative(&mut resolved, &contigs))
+            {
+                statuses.push((written, format!("invalid ({e})")));
+                continue;
+            }
+            if resolved.is_empty() {
+                statuses.push((written, String::from("no matching contigs")));
+            }
+            for (region, _, _) in resolved {
+                let Some(&length) = contigs.get(region.name()) else {
+                    statuses.push((region.to_string(), String::from("missing contig")));
+                    continue;
+                };
+                let interval = region.interval();
+                let past_end = |position: Option<Position>| {
+                    position.is_some_and(|position| usize::from(position) as u64 > length)
+                };
+                let status = if past_end(interval.start()) || past_end(interval.end()) {
+                    format!("out of bounds ({} has {length} bases)", region.name())
+                } else if !circular && Self::wraps(&region).is_some() {
+                    String::from("starts after end")
                 } else {
-                    let mut reverse = false;
-                    let mut region = region.to_string();
-                    if region.starts_with('-') {
-                        reverse = true;
-                        region = region[1..].to_string();
+                    String::from("ok")
+                };
+                statuses.push((region.to_string(), status));
+            }
+        }
+        Ok(statuses)
+    }
+
+    // Keep a random sample of regions, in the order they were given. A seed
+    // picks the same sample on every run.
+    pub fn sample_regions(
+        regions: &mut Vec<(Region, bool, Option<String>)>,
+        size: usize,
+        seed: Option<u64>,
+    ) {
+        if size >= regions.len() {
+            return;
+        }
+        let mut rng: StdRng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => rand::make_rng(),
+        };
+        let mut indices = rand::seq::index::sample(&mut rng, regions.len(), size).into_vec();
+        indices.sort_unstable();
+        *regions = indices.into_iter().map(|i| regions[i].clone()).collect();
+    }
+
+    // Replace regions that are only a name found in the name map (e.g.,
+    // BRCA1) with the region it maps to, naming the output record after the
+    // name. A reverse complemented name gives the reverse complement of the
+    // mapped region's strand.
+    pub fn resolve_names(
+        regions: &mut [(Region, bool, Option<String>)],
+        name_map: &HashMap<String, (Region, bool)>,
+    ) {
+        for (region, reversed, name) in regions {
+            let interval = region.interval();
+            if interval.start().is_some() || interval.end().is_some() {
+                continue;
+            }
+            if let Some((mapped, mapped_reversed)) = name_map.get(region.name()) {
+                name.get_or_insert_with(|| region.name().to_string());
+                *region = mapped.clone();
+                *reversed ^= mapped_reversed;
+            }
+        }
+    }
+
+    // The other names a contig may go by, from the alias file and then the
+    // naming convention, in the order to try them.
+    fn contig_aliases(name: &str, options: &ExtractOptions) -> Vec<String> {
+        let mut aliases = options.chr_alias.get(name).cloned().unwrap_or_default();
+        aliases.extend(options.contig_naming.and_then(|naming| naming.rename(name)));
+        aliases
+    }
+
+    // Rename the contigs of regions that aren't in the FASTA index (e.g.,
+    // chr1 for a reference that names it 1) to their first alias that is.
+    // Relative regions, which are parsed as a name (e.g., chr1:10%-90%), have
+    // their contig renamed and keep their coordinates. Contigs with no alias
+    // in the index are left to be reported as missing.
+    fn alias_contigs(
+        regions: &mut [(Region, bool, Option<String>)],
+        blocks: &mut Blocks,
+        options: &ExtractOptions,
+        contigs: &HashMap<&str, u64>,
+    ) {
+        if options.chr_alias.is_empty() && options.contig_naming.is_none() {
+            return;
+        }
+        let alias = |name: &str| {
+            Self::contig_aliases(name, options)
+                .into_iter()
+                .find(|alias| contigs.contains_key(alias.as_str()))
+        };
+        for (region, _, name) in regions {
+            if contigs.contains_key(region.name()) {
+                continue;
+            }
+            let interval = region.interval();
+            if let Some(contig) = alias(region.name()) {
+                Self::rename_contig(region, name, &contig, blocks);
+            } else if let (None, None, Some((contig, coordinates))) = (
+                interval.start(),
+                interval.end(),
+                region.name().rsplit_once(':'),
+            ) {
+                if let Some(contig) = alias(contig) {
+                    *region = Region::new(format!("{contig}:{coordinates}"), ..);
+                }
+            }
+        }
+    }
+
+    // Rename the contig of a region. The BED12 blocks of the region, which
+    // are looked up by it, are moved to the renamed region, on the renamed
+    // contig.
+    fn rename_contig(
+        region: &mut Region,
+        name: &Option<String>,
+        contig: &str,
+        blocks: &mut Blocks,
+    ) {
+        let renamed = Region::new(contig, region.interval());
+        if let Some(moved) = blocks.remove(&Self::block_key(region, name)) {
+            let moved = moved
+                .iter()
+                .map(|block| Region::new(contig, block.interval()))
+                .collect();
+            blocks.insert(Self::block_key(&renamed, name), moved);
+        }
+        *region = renamed;
+    }
+
+    // The MD5 digest of a region's contig given by digest, lowercased. A
+    // digest is given as md5:<digest>, as in CRAM reference registries
+    // (e.g., md5:1b22b98cdeb4a9304cb5d48026a85128:1-100).
+    fn digest_of(name: &str) -> Option<String> {
+        name.strip_prefix("md5:")
+            .filter(|digest| digest.len() == 32 && digest.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(str::to_ascii_lowercase)
+    }
+
+    // Replace the contigs of regions given by digest with the contig names
+    // they're listed with (e.g., from --contig-md5), keyed by digest.
+    fn resolve_digests(
+        regions: &mut [(Region, bool, Option<String>)],
+        blocks: &mut Blocks,
+        digests: &HashMap<String, String>,
+    ) {
+        if digests.is_empty() {
+            return;
+        }
+        for (region, _, name) in regions {
+            if let Some(contig) = Self::digest_of(region.name()).and_then(|d| digests.get(&d)) {
+                Self::rename_contig(region, name, contig, blocks);
+            }
+        }
+    }
+
+    // Replace the contigs of the remaining regions given by digest with the
+    // contigs in the index with that MD5 digest (of the uppercased sequence,
+    // as for the M5 tag of SAM/CRAM headers). Contigs are digested only when
+    // a region needs it, in index order until every digest is found, and
+    // their digests are cached (by contig name) for later extractions. A
+    // contig literally named like a digest is taken as named.
+    fn digest_contigs(
+        regions: &mut [(Region, bool, Option<String>)],
+        blocks: &mut Blocks,
+        reader: &mut IndexedReader<Box<dyn BufReadSeek>>,
+        cache: &mut HashMap<String, String>,
+    ) -> Result<()> {
+        let names: Vec<String> = reader
+            .index()
+            .iter()
+            .map(|record| record.name().to_string())
+            .collect();
+        for (region, _, name) in regions {
+            let Some(digest) = Self::digest_of(region.name()) else {
+                continue;
+            };
+            if names.iter().any(|contig| contig == region.name()) {
+                continue;
+            }
+            let mut found = cache
+                .iter()
+                .find(|(_, cached)| **cached == digest)
+                .map(|(contig, _)| contig.clone());
+            for contig in &names {
+                if found.is_some() {
+                    break;
+                }
+                if cache.contains_key(contig) {
+                    continue;
+                }
+                let record = reader.query(&Region::new(contig.as_str(), ..))?;
+                let contig_digest = Checksum::Md5.digest([record.sequence().as_ref()]);
+                if contig_digest == digest {
+                    found = Some(contig.clone());
+                }
+                cache.insert(contig.clone(), contig_digest);
+            }
+            let Some(contig) = found else {
+                bail!("no contig in the FASTA file has the MD5 digest {digest}");
+            };
+            Self::rename_contig(region, name, &contig, blocks);
+        }
+        Ok(())
+    }
+
+    // Whether a region's contig is a pattern: a regular expression between
+    // slashes, or a glob with *, ?, or [.
+    fn is_pattern(name: &str) -> bool {
+        (name.len() > 1 && name.starts_with('/') && name.ends_with('/'))
+            || name.contains(['*', '?', '['])
+    }
+
+    // Split the name of a region on a contig pattern into the pattern and
+    // any coordinates kept in the name (absolute or relative coordinates, or
+    // the first or last N bases, after the last colon). Regions on a single
+    // contig return None.
+    fn split_pattern(name: &str) -> Option<(&str, Option<&str>)> {
+        let is_coordinates = |coordinates: &str| {
+            Self::first_or_last(coordinates).is_some()
+                || (!coordinates.is_empty()
+                    && coordinates
+                        .chars()
+                        .all(|c| c.is_ascii_digit() || matches!(c, '-' | '%' | '.')))
+        };
+        match name.rsplit_once(':') {
+            Some((pattern, coordinates))
+                if Self::is_pattern(pattern) && is_coordinates(coordinates) =>
+            {
+                Some((pattern, Some(coordinates)))
+            }
+            _ if Self::is_pattern(name) => Some((name, None)),
+            _ => None,
+        }
+    }
+
+    // Expand regions whose contig is a pattern into a region on each contig
+    // in the index it matches, in index order, keeping the region's
+    // coordinates, strand, and name. A regular expression (e.g.,
+    // /scaffold_\d+/) matches anywhere in a contig name unless anchored, and
+    // a glob (e.g., chr*) matches the whole name. A pattern's coordinates are
+    // parsed as part of its name (e.g., chr*:1-100), so a contig named like
+    // the whole region (e.g., HLA-A*01:01:01:01) is taken literally, as is a
+    // contig named like the pattern. A pattern that matches no contigs is
+    // skipped with a warning.
+    fn expand_patterns(
+        regions: &mut Vec<(Region, bool, Option<String>)>,
+        names: &[&str],
+        contigs: &HashMap<&str, u64>,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<()> {
+        if !regions
+            .iter()
+            .any(|(region, _, _)| Self::split_pattern(region.name()).is_some())
+        {
+            return Ok(());
+        }
+        let mut matches: HashMap<String, Vec<&str>> = HashMap::new();
+        let mut expanded = Vec::with_capacity(regions.len());
+        for (region, reversed, name) in regions.drain(..) {
+            let split = Self::split_pattern(region.name())
+                .filter(|_| !contigs.contains_key(region.name()))
+                .map(|(pattern, coordinates)| {
+                    (pattern.to_string(), coordinates.map(str::to_string))
+                });
+            let Some((pattern, coordinates)) = split else {
+                expanded.push((region, reversed, name));
+                continue;
+            };
+            let (pattern, coordinates) = (pattern.as_str(), coordinates.as_deref());
+            if let Some((&contig, _)) = contigs.get_key_value(pattern) {
+                matches.insert(pattern.to_string(), vec![contig]);
+            } else if !matches.contains_key(pattern) {
+                let regex = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+                    Some(regex) => Regex::new(regex),
+                    None => Regex::new(&Self::glob_regex(pattern)),
+                }
+                .with_context(|| format!("invalid contig pattern {pattern}"))?;
+                let matched: Vec<&str> = names
+                    .iter()
+                    .copied()
+                    .filter(|name| regex.is_match(name))
+                    .collect();
+                if matched.is_empty() {
+                    diagnostics.push(Diagnostic::Warning(format!(
+                        "skipping {pattern}, which matches no contigs"
+                    )));
+                }
+                matches.insert(pattern.to_string(), matched);
+            }
+            for contig in &matches[pattern] {
+                // Relative coordinates stay in the name until they're
+                // resolved.
+                let region = match coordinates {
+                    Some(coordinates) if coordinates.contains('%') => {
+                        Region::new(format!("{contig}:{coordinates}"), ..)
                     }
+                    Some(coordinates) if Self::first_or_last(coordinates).is_some() => {
+                        Region::new(format!("{contig}:{coordinates}"), ..)
+                    }
+                    Some(coordinates) => {
+                        let interval: Interval = coordinates
+                            .parse()
+                            .with_context(|| format!("invalid region {}", region.name()))?;
+                        Region::new(*contig, interval)
+                    }
+                    None => Region::new(*contig, region.interval()),
+                };
+                expanded.push((region, reversed, name.clone()));
+            }
+        }
+        *regions = expanded;
+        Ok(())
+    }
 
-                    if let Ok(region) = region.parse() {
-                        Some((region, reverse))
-                    } else {
-                        None
+    // Translate a glob into an anchored regular expression: * matches any
+    // characters, ? matches one, and [...] (or [!...]) matches a set.
+    fn glob_regex(glob: &str) -> String {
+        let mut regex = String::from("^");
+        let mut in_set = false;
+        for c in glob.chars() {
+            match (c, in_set) {
+                ('*', false) => regex.push_str(".*"),
+                ('?', false) => regex.push('.'),
+                ('[', false) => {
+                    in_set = true;
+                    regex.push('[');
+                }
+                ('!', true) if regex.ends_with('[') => regex.push('^'),
+                (']', true) => {
+                    in_set = false;
+                    regex.push(']');
+                }
+                (c, true) => regex.push(c),
+                (c, false) => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        regex
+    }
+
+    // Replace regions with relative coordinates (e.g., chr1:10%-90%), which
+    // are parsed as a name until contig lengths are known, with the region
+    // they cover. A percentage start is the base after that fraction of the
+    // contig and a percentage end is the last base within it, so 10%-90% of
+    // a 1000-base contig is 101-900; absolute and relative coordinates can be
+    // mixed (e.g., chr1:1000-90%). The first or last N bases of a contig
+    // (chr1:first=N or chr1:last=N) are resolved the same way, and are the
+    // whole contig if it's shorter than N.
+    fn resolve_relative(
+        regions: &mut [(Region, bool, Option<String>)],
+        contigs: &HashMap<&str, u64>,
+    ) -> Result<()> {
+        for (region, _, _) in regions {
+            let interval = region.interval();
+            if interval.start().is_some() || interval.end().is_some() {
+                continue;
+            }
+            let Some((contig, coordinates)) = region.name().rsplit_once(':') else {
+                continue;
+            };
+            let Some(&length) = contigs.get(contig) else {
+                continue;
+            };
+            if contigs.contains_key(region.name()) {
+                continue;
+            }
+            if let Some((end, bases)) = Self::first_or_last(coordinates) {
+                let bases: u64 = bases
+                    .parse()
+                    .ok()
+                    .filter(|&bases| bases > 0)
+                    .with_context(|| format!("invalid number of bases in region {region}"))?;
+                if length == 0 {
+                    bail!("region {region} is on an empty contig");
+                }
+                let (start, end) = match end {
+                    "first" => (1, bases.min(length)),
+                    _ => (length.saturating_sub(bases) + 1, length),
+                };
+                let start = Position::try_from(start as usize)?;
+                let end = Position::try_from(end as usize)?;
+                *region = Region::new(contig, start..=end);
+                continue;
+            }
+            if !coordinates.contains('%') {
+                continue;
+            }
+
+            let position = |coordinate: &str, start: bool| -> Result<Position> {
+                let position = match coordinate.strip_suffix('%') {
+                    Some(percentage) => {
+                        let percentage: f64 = percentage
+                            .parse()
+                            .ok()
+                            .filter(|percentage| (0.0..=100.0).contains(percentage))
+                            .with_context(|| format!("invalid percentage in region {region}"))?;
+                        let bases = (length as f64 * percentage / 100.0) as usize;
+                        if start {
+                            bases + 1
+                        } else {
+                            bases.max(1)
+                        }
                     }
+                    None => coordinate
+                        .parse()
+                        .with_context(|| format!("invalid position in region {region}"))?,
+                };
+                Ok(Position::try_from(position)?)
+            };
+            *region = match coordinates.split_once('-') {
+                Some((start, end)) => {
+                    Region::new(contig, position(start, true)?..=position(end, false)?)
                 }
+                None => Region::new(contig, position(coordinates, true)?..),
+            };
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // Write a FASTA file to a temporary directory, returning its path.
+    pub(super) fn write_fasta(dir: &tempfile::TempDir, contents: &str) -> String {
+        let path = dir.path().join("ref.fa");
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // Extract SAMtools-style regions from a FASTA file, returning each
+    // record's name and sequence.
+    fn extract(fasta: &str, regions: &[&str], options: ExtractOptions) -> Vec<(String, String)> {
+        let regions = regions
+            .iter()
+            .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+            .collect();
+        let mut sequences = Sequences::from_regions(fasta, regions).unwrap();
+        sequences.extract(options).unwrap();
+        sequences
+            .records()
+            .map(|record| {
+                let sequence = str::from_utf8(record.sequence().as_ref()).unwrap();
+                (record.name().to_string(), sequence.to_string())
             })
+            .collect()
+    }
+
+    #[test]
+    fn soft_masking_is_kept_on_the_minus_strand() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCacgtGGTT\n");
+        let records = extract(
+            &fasta,
+            &["-chr1:5-8", "-chr1:3-6"],
+            ExtractOptions::default(),
+        );
+        assert_eq!(
+            records,
+            [
+                (String::from("chr1:5-8"), String::from("acgt")),
+                (String::from("chr1:3-6"), String::from("gtGG")),
+            ]
+        );
+    }
+
+    #[test]
+    fn hard_masking_follows_the_reference_before_complementing() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAAccGT\n");
+        let options = ExtractOptions {
+            hard_mask: true,
+            complement_table: HashMap::from([(b'A', b't')]),
+            ..Default::default()
+        };
+        let records = extract(&fasta, &["-chr1:1-6"], options);
+        assert_eq!(
+            records,
+            [(String::from("chr1:1-6"), String::from("ACNNtt"))]
+        );
+    }
+
+    #[test]
+    fn flanks_are_genomic_and_clamped_near_a_contig_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCGGTTAC\n");
+        let options = ExtractOptions {
+            flank_left: 5,
+            flank_right: 2,
+            ..Default::default()
+        };
+        let records = extract(&fasta, &["-chr1:2-4"], options);
+        assert_eq!(
+            records,
+            [(String::from("chr1:1-6"), String::from("CCGGTT"))]
+        );
+    }
+
+    #[test]
+    fn records_keep_the_order_and_multiplicity_of_regions() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCGGTTAC\n>chr2\nGGGG\n");
+        let records = extract(
+            &fasta,
+            &["chr1:5-8", "chr1:1-6", "chr1:9-10"],
+            ExtractOptions::default(),
+        );
+        assert_eq!(
+            records,
+            [
+                (String::from("chr1:5-8"), String::from("GGTT")),
+                (String::from("chr1:1-6"), String::from("AACCGG")),
+                (String::from("chr1:9-10"), String::from("AC")),
+            ]
+        );
+    }
+
+    // Resolve relative coordinates in SAMtools-style regions against contig
+    // lengths, returning the resolved regions.
+    fn resolve_relative(regions: &[&str], contigs: &[(&str, u64)]) -> Result<Vec<String>> {
+        let mut regions: Vec<_> = regions
+            .iter()
+            .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+            .collect();
+        let contigs = contigs.iter().copied().collect();
+        Sequences::resolve_relative(&mut regions, &contigs)?;
+        Ok(regions
+            .iter()
+            .map(|(region, _, _)| region.to_string())
             .collect())
     }
+
+    #[test]
+    fn relative_coordinates_resolve_against_contig_lengths() {
+        let contigs = [("chr1", 1000), ("chr2", 7)];
+        let resolved = resolve_relative(
+            &[
+                "chr1:10%-90%",
+                "chr1:0%-100%",
+                "chr1:100-50%",
+                "chr1:25%-600",
+                "chr1:75%",
+                "chr2:50%-100%",
+                "chr2:1-3",
+            ],
+            &contigs,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            [
+                "chr1:101-900",
+                "chr1:1-1000",
+                "chr1:100-500",
+                "chr1:251-600",
+                "chr1:751",
+                "chr2:4-7",
+                "chr2:1-3",
+            ]
+        );
+        assert!(resolve_relative(&["chr1:10%-101%"], &contigs).is_err());
+    }
+
+    #[test]
+    fn aliased_bed12_regions_keep_their_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">1\nAAAACCCCGGGGTTTT\n");
+        let bed = dir.path().join("tx.bed");
+        fs::write(
+            &bed,
+            "chr1\t2\t13\ttx\t0\t+\t2\t13\t0\t2\t2,3,\t0,8,\n\
+             chr1\t2\t13\ttx\t0\t-\t2\t13\t0\t2\t2,3,\t0,8,\n",
+        )
+        .unwrap();
+        let mut sequences = Sequences::new(
+            &fasta,
+            &[bed.to_string_lossy().into_owned()],
+            None,
+            Coordinates::default(),
+            true,
+        )
+        .unwrap();
+        let options = ExtractOptions {
+            contig_naming: Some(ContigNaming::Ensembl),
+            ..Default::default()
+        };
+        sequences.extract(options).unwrap();
+        let records: Vec<&[u8]> = sequences
+            .records()
+            .map(|record| record.sequence().as_ref())
+            .collect();
+        assert_eq!(records, [&b"AAGGT"[..], b"ACCTT"]);
+        assert_eq!(
+            sequences.blocks.values().collect::<Vec<_>>(),
+            [&vec![
+                "1:3-4".parse::<Region>().unwrap(),
+                "1:11-13".parse().unwrap()
+            ]]
+        );
+    }
+
+    #[test]
+    fn bed12_regions_on_digests_keep_their_blocks() {
+        const DIGEST: &str = "2a9fd43653a81f9ec44e34c7ec038636";
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAAAACCCCGGGGTTTT\n");
+        let bed = dir.path().join("tx.bed");
+        fs::write(
+            &bed,
+            format!("md5:{DIGEST}\t2\t13\ttx\t0\t-\t2\t13\t0\t2\t2,3,\t0,8,\n"),
+        )
+        .unwrap();
+        // The digest is found by digesting contigs, or listed with its name.
+        for contig_md5 in [
+            HashMap::new(),
+            HashMap::from([(String::from(DIGEST), String::from("chr1"))]),
+        ] {
+            let mut sequences = Sequences::new(
+                &fasta,
+                &[bed.to_string_lossy().into_owned()],
+                None,
+                Coordinates::default(),
+                true,
+            )
+            .unwrap();
+            let options = ExtractOptions {
+                contig_md5,
+                ..Default::default()
+            };
+            sequences.extract(options).unwrap();
+            let records: Vec<&[u8]> = sequences
+                .records()
+                .map(|record| record.sequence().as_ref())
+                .collect();
+            assert_eq!(records, [b"ACCTT"]);
+        }
+    }
+
+    #[test]
+    fn first_and_last_bases_are_clamped_to_the_contig() {
+        let contigs = [("chr1", 1000), ("chr2", 7), ("empty", 0)];
+        let resolved = resolve_relative(
+            &[
+                "chr1:first=100",
+                "chr1:last=100",
+                "chr2:first=100",
+                "chr2:last=100",
+                "chr2:first=7",
+                "chr2:last=1",
+            ],
+            &contigs,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            [
+                "chr1:1-100",
+                "chr1:901-1000",
+                "chr2:1-7",
+                "chr2:1-7",
+                "chr2:1-7",
+                "chr2:7-7",
+            ]
+        );
+        assert!(resolve_relative(&["chr2:first=0"], &contigs).is_err());
+        assert!(resolve_relative(&["chr2:last=x"], &contigs).is_err());
+        assert!(resolve_relative(&["empty:first=10"], &contigs).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr2\nACGTTGA\n");
+        let records = extract(
+            &fasta,
+            &["chr2:first=100", "-chr2:last=100"],
+            ExtractOptions::default(),
+        );
+        assert_eq!(
+            records,
+            [
+                (String::from("chr2:1-7"), String::from("ACGTTGA")),
+                (String::from("chr2:1-7"), String::from("TCAACGT")),
+            ]
+        );
+    }
+
+    #[test]
+    fn contigs_named_like_patterns_are_taken_literally() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(
+            &dir,
+            ">HLA-A*01:01:01:01\nACGTACGT\n>HLA-A*02:01\nGGGG\n>chr1\nTTTT\n",
+        );
+        let regions = [
+            "HLA-A*01:01:01:01",
+            "HLA-A*01:01:01:01:2-4",
+            "HLA-A*:1-2",
+            "/^chr/",
+        ];
+        let expected = [
+            ("HLA-A*01:01:01:01", "ACGTACGT"),
+            ("HLA-A*01:01:01:01:2-4", "CGT"),
+            ("HLA-A*01:01:01:01:1-2", "AC"),
+            ("HLA-A*02:01:1-2", "GG"),
+            ("chr1", "TTTT"),
+        ];
+        let records = extract(&fasta, &regions, ExtractOptions::default());
+        let records: Vec<(&str, &str)> = records
+            .iter()
+            .map(|(name, sequence)| (name.as_str(), sequence.as_str()))
+            .collect();
+        assert_eq!(records, expected);
+
+        // Without the index, contigs named like patterns are still found,
+        // but patterns can't be expanded.
+        let options = ExtractOptions {
+            no_index: true,
+            ..Default::default()
+        };
+        let records = extract(&fasta, &regions[..2], options);
+        assert_eq!(
+            records,
+            [
+                (String::from("HLA-A*01:01:01:01"), String::from("ACGTACGT")),
+                (String::from("HLA-A*01:01:01:01:2-4"), String::from("CGT")),
+            ]
+        );
+        let regions = vec![Sequences::parse_region("HLA-A*", Coordinates::OneBased).unwrap()];
+        let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+        let options = ExtractOptions {
+            no_index: true,
+            ..Default::default()
+        };
+        assert!(sequences.extract(options).is_err());
+    }
+
+    #[test]
+    fn excluded_spans_are_subtracted_from_regions() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCGGTTAC\n");
+        let exclude = ["chr1:4-5", "chr1:9-20"]
+            .iter()
+            .map(|region| region.parse().unwrap())
+            .collect();
+        let regions = [
+            "chr1:1-10",
+            "-chr1:1-10",
+            "chr1:3-6",
+            "chr1:4-5",
+            "chr1:9-10",
+        ]
+        .iter()
+        .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+        .collect();
+        let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+        let options = ExtractOptions {
+            exclude,
+            ..Default::default()
+        };
+        sequences.extract(options).unwrap();
+        let records: Vec<(&str, &[u8])> = sequences
+            .records()
+            .map(|record| (record.name(), record.sequence().as_ref()))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("chr1:1-3", &b"AAC"[..]),
+                ("chr1:6-8", b"GTT"),
+                ("chr1:6-8", b"AAC"),
+                ("chr1:1-3", b"GTT"),
+                ("chr1:3-3", b"C"),
+                ("chr1:6-6", b"G"),
+            ]
+        );
+        assert_eq!(
+            sequences.take_diagnostics(),
+            [
+                Diagnostic::Warning(String::from(
+                    "skipping region chr1:4-5, which is excluded entirely"
+                )),
+                Diagnostic::Warning(String::from(
+                    "skipping region chr1:9-10, which is excluded entirely"
+                )),
+            ]
+        );
+        // The stats count the regions given, not the pieces left of them.
+        let stats = sequences.stats();
+        assert_eq!((stats.requested, stats.extracted), (5, 6));
+    }
+
+    #[test]
+    fn counts_only_include_records_that_pass_the_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nGGGGAAAA\n>chr2\nCCCC\n");
+        let counts = |filter: Option<&str>| {
+            let regions = ["chr1:1-4", "chr1:5-8", "chr2", "chr1"]
+                .iter()
+                .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+                .collect();
+            let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+            let options = ExtractOptions {
+                filter: filter.map(|filter| Filter::parse(filter).unwrap()),
+                ..Default::default()
+            };
+            sequences.count_bases(options).unwrap()
+        };
+        assert_eq!(
+            counts(None),
+            [(String::from("chr1"), 16), (String::from("chr2"), 4)]
+        );
+        assert_eq!(
+            counts(Some("gc > 0.4")),
+            [(String::from("chr1"), 12), (String::from("chr2"), 4)]
+        );
+        assert_eq!(
+            counts(Some("len < 8")),
+            [(String::from("chr1"), 8), (String::from("chr2"), 4)]
+        );
+    }
+
+    #[test]
+    fn iupac_codes_are_complemented() {
+        let pairs = [
+            (b'A', b'T'),
+            (b'C', b'G'),
+            (b'G', b'C'),
+            (b'T', b'A'),
+            (b'R', b'Y'),
+            (b'Y', b'R'),
+            (b'K', b'M'),
+            (b'M', b'K'),
+            (b'S', b'S'),
+            (b'W', b'W'),
+            (b'B', b'V'),
+            (b'V', b'B'),
+            (b'D', b'H'),
+            (b'H', b'D'),
+            (b'N', b'N'),
+        ];
+        for (base, complement) in pairs {
+            for (base, complement) in [
+                (base, complement),
+                (base.to_ascii_lowercase(), complement.to_ascii_lowercase()),
+            ] {
+                let sequence = Sequence::from(vec![base]);
+                let reversed =
+                    Sequences::reverse_complement(&sequence, Alphabet::Dna, &HashMap::new())
+                        .unwrap();
+                assert_eq!(reversed.as_ref(), [complement], "{}", char::from(base));
+            }
+        }
+
+        let sequence = Sequence::from(b"ACGTRYKMSWBDHVNacgtrykmswbdhvn".to_vec());
+        let reversed =
+            Sequences::reverse_complement(&sequence, Alphabet::Dna, &HashMap::new()).unwrap();
+        assert_eq!(reversed.as_ref(), b"nbdhvwskmryacgtNBDHVWSKMRYACGT");
+    }
+
+    #[test]
+    fn strands_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCGGTTAC\n");
+        let regions = ["chr1:1-4", "-chr1:1-4"]
+            .iter()
+            .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+            .collect();
+        let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+        let options = ExtractOptions {
+            annotate: true,
+            ..Default::default()
+        };
+        sequences.extract(options).unwrap();
+        let descriptions: Vec<Option<&str>> = sequences
+            .records()
+            .map(|record| record.description())
+            .collect();
+        assert_eq!(
+            descriptions,
+            [Some("chr1:1-4 strand=+"), Some("chr1:1-4 strand=-")]
+        );
+        assert_eq!(sequences.stats().reversed, 1);
+    }
+
+    #[test]
+    fn whole_contigs_keep_their_descriptions() {
+        let dir = tempfile::tempdir().unwrap();
+        let long = "x".repeat(1000);
+        let fasta = write_fasta(
+            &dir,
+            &format!(">chr1 Homo sapiens chromosome 1\nACGT\n>chr2\nGG\n>chr3 {long}\nTT\n"),
+        );
+        let descriptions = |regions: &[&str], annotate| {
+            let regions = regions
+                .iter()
+                .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+                .collect();
+            let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+            let options = ExtractOptions {
+                keep_description: true,
+                annotate,
+                ..Default::default()
+            };
+            sequences.extract(options).unwrap();
+            sequences
+                .records()
+                .map(|record| record.description().map(String::from))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            descriptions(&["chr1", "chr2", "chr3", "chr1:1-2"], false),
+            [
+                Some(String::from("Homo sapiens chromosome 1")),
+                None,
+                Some(long),
+                None,
+            ]
+        );
+        assert_eq!(
+            descriptions(&["-chr1"], true),
+            [Some(String::from(
+                "chr1 strand=- Homo sapiens chromosome 1"
+            ))]
+        );
+    }
+
+    #[test]
+    fn record_names_are_formatted() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAACCGGTTAC\n>chr2\nGGG\n");
+        let regions = ["chr1:1-4", "promoter=-chr1:3-6", "chr2"];
+        let names = |format: &str| {
+            let options = ExtractOptions {
+                name_format: Some(HeaderTemplate::parse_name(format).unwrap()),
+                ..Default::default()
+            };
+            extract(&fasta, &regions, options)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names("{name}_{start}_{end}"),
+            ["chr1_1_4", "promoter_3_6", "chr2_1_3"]
+        );
+        assert_eq!(
+            names("{contig}|{start}-{end}({strand})|{length}bp"),
+            ["chr1|1-4(+)|4bp", "chr1|3-6(-)|4bp", "chr2|1-3(+)|3bp"]
+        );
+        assert_eq!(
+            names("{{{region}}}"),
+            ["{chr1:1-4}", "{chr1:3-6}", "{chr2}"]
+        );
+
+        assert!(HeaderTemplate::parse_name("{name} {start}").is_err());
+        assert!(HeaderTemplate::parse_name("{name}_{stop}").is_err());
+        assert!(HeaderTemplate::parse_name("{name").is_err());
+    }
+
+    #[test]
+    fn windows_step_along_regions_on_either_strand() {
+        let contigs = HashMap::from([("chr1", 1000)]);
+        let region: Region = "chr1:101-150".parse().unwrap();
+        let windows = |reversed, keep_partial| {
+            let regions = vec![(region.clone(), reversed, None)];
+            Sequences::window_regions(regions, 25, 7, keep_partial, &contigs)
+                .unwrap()
+                .into_iter()
+                .map(|(window, is_reversed, name)| {
+                    assert_eq!(is_reversed, reversed);
+                    (window.to_string(), name.unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+        let named = |windows: &[&str]| {
+            windows
+                .iter()
+                .enumerate()
+                .map(|(i, window)| (window.to_string(), format!("chr1:101-150_w{}", i + 1)))
+                .collect::<Vec<_>>()
+        };
+
+        let forward = [
+            "chr1:101-125",
+            "chr1:108-132",
+            "chr1:115-139",
+            "chr1:122-146",
+            "chr1:129-150",
+        ];
+        assert_eq!(windows(false, false), named(&forward[..4]));
+        assert_eq!(windows(false, true), named(&forward));
+
+        let reversed = [
+            "chr1:126-150",
+            "chr1:119-143",
+            "chr1:112-136",
+            "chr1:105-129",
+            "chr1:101-122",
+        ];
+        assert_eq!(windows(true, false), named(&reversed[..4]));
+        assert_eq!(windows(true, true), named(&reversed));
+    }
+
+    #[test]
+    fn bed12_blocks_are_spliced_on_either_strand() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nAAAACCCCGGGGTTTT\n");
+        let bed = dir.path().join("tx.bed");
+        fs::write(
+            &bed,
+            "chr1\t2\t13\tplus\t0\t+\t2\t13\t0\t2\t2,3,\t0,8,\n\
+             chr1\t2\t13\tminus\t0\t-\t2\t13\t0\t2\t2,3,\t0,8,\n",
+        )
+        .unwrap();
+        let mut sequences = Sequences::new(
+            &fasta,
+            &[bed.to_string_lossy().into_owned()],
+            None,
+            Coordinates::default(),
+            true,
+        )
+        .unwrap();
+        sequences.extract(ExtractOptions::default()).unwrap();
+        let records: Vec<(&str, &[u8])> = sequences
+            .records()
+            .map(|record| (record.name(), record.sequence().as_ref()))
+            .collect();
+        assert_eq!(records, [("plus", &b"AAGGT"[..]), ("minus", b"ACCTT")]);
+    }
+
+    #[test]
+    fn contigs_are_aliased_between_naming_conventions() {
+        let dir = tempfile::tempdir().unwrap();
+        let named = |naming| ExtractOptions {
+            contig_naming: Some(naming),
+            ..ExtractOptions::default()
+        };
+
+        let ucsc = write_fasta(&dir, ">chr1\nACGTAC\n>chrM\nGGCC\n");
+        let records = extract(
+            &ucsc,
+            &["1:1-4", "MT:2-3", "chr1:5-6", "chrM"],
+            named(ContigNaming::Ucsc),
+        );
+        assert_eq!(
+            records,
+            [
+                (String::from("chr1:1-4"), String::from("ACGT")),
+                (String::from("chrM:2-3"), String::from("GC")),
+                (String::from("chr1:5-6"), String::from("AC")),
+                (String::from("chrM"), String::from("GGCC")),
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let ensembl = write_fasta(&dir, ">1\nACGTAC\n>MT\nGGCC\n");
+        let records = extract(
+            &ensembl,
+            &["chr1:1-4", "chrM:2-3", "1:5-6"],
+            named(ContigNaming::Ensembl),
+        );
+        assert_eq!(
+            records,
+            [
+                (String::from("1:1-4"), String::from("ACGT")),
+                (String::from("MT:2-3"), String::from("GC")),
+                (String::from("1:5-6"), String::from("AC")),
+            ]
+        );
+
+        // An alias file's names are tried before the naming convention's.
+        let options = ExtractOptions {
+            chr_alias: HashMap::from([(String::from("mito"), vec![String::from("MT")])]),
+            ..named(ContigNaming::Ensembl)
+        };
+        let records = extract(&ensembl, &["mito:1-2"], options);
+        assert_eq!(records, [(String::from("MT:1-2"), String::from("GG"))]);
+    }
 }
@@ -0,0 +1,1405 @@
+// Writing extracted records: as FASTA, FASTQ, 2bit, JSON, or binpacked, to
+// stdout, a file, or a file per record, merged or not, with the files that
+// describe them (provenance, merged contig BED and GFF3, and checksums).
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::HashMap,
+    fs::{create_dir_all, File, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    slice,
+    sync::mpsc,
+    thread,
+};
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use noodles::{
+    bgzf,
+    core::Position,
+    fasta::{self, Record},
+    fastq, gff,
+};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{binpacked, checksum::Checksum, header::HeaderTemplate, twobit, Diagnostic};
+
+use super::{ExtractOptions, Extracted, Sequences};
+
+// The format of the output file.
+#[derive(Clone, Copy)]
+pub enum OutputFormat {
+    // FASTA
+    Fasta,
+    // FASTQ with a fixed quality character for every base
+    Fastq,
+    // UCSC 2bit, with lowercase bases soft-masked (only A, C, G, T, and N can be written)
+    TwoBit,
+    // JSON: an array of objects with each record's name, region, strand, sequence, and length
+    // (a single object for a merged contig)
+    Json,
+    // each record's length as a little-endian u32, then its bases, with no names or line breaks
+    BinPacked,
+}
+
+// An order to sort output records in.
+#[derive(Clone, Copy)]
+pub enum SortOrder {
+    // by record name
+    Name,
+    // by contig (in the order of the FASTA index), then start and end
+    Coord,
+    // by length, longest first
+    Length,
+}
+
+// The options for writing extracted sequences, as described in
+// `Sequences::write`.
+pub struct OutputOptions {
+    pub output_location: Option<String>,
+    pub merge: bool,
+    pub contig_name: Option<String>,
+    pub gap_size: usize,
+    pub gap_char: u8,
+    pub gap_seq: Option<Vec<u8>>,
+    pub format: OutputFormat,
+    pub quality: u8,
+    pub line_width: usize,
+    pub split_dir: Option<String>,
+    pub bgzip: bool,
+    pub plain_gzip: bool,
+    pub index: bool,
+    pub group_by: Option<Regex>,
+    pub provenance: Option<String>,
+    pub merge_bed: Option<String>,
+    pub merge_gff: Option<String>,
+    pub force: bool,
+    pub append: bool,
+    pub checksum: Option<Checksum>,
+    pub checksum_file: Option<String>,
+    pub order: HashMap<String, usize>,
+    pub sort: Option<SortOrder>,
+    pub split_strand: bool,
+    pub trim_n: bool,
+    pub warn_gaps: Option<usize>,
+    pub header_template: Option<HeaderTemplate>,
+    pub buffer_size: usize,
+}
+
+impl OutputOptions {
+    // The gap between merged records: the literal gap sequence, if given,
+    // or gap_size repeats of the gap character.
+    fn gap(&self) -> Vec<u8> {
+        match &self.gap_seq {
+            Some(gap_seq) => gap_seq.clone(),
+            None => vec![self.gap_char; self.gap_size],
+        }
+    }
+}
+
+// A record written as JSON. Merged contigs have no single region or
+// strand, so both are null for them.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    name: &'a str,
+    region: Option<String>,
+    strand: Option<char>,
+    sequence: Cow<'a, str>,
+    length: usize,
+}
+
+// A contig to write: an extracted record, or extracted records merged into
+// a single named contig, by their index in the extracted records.
+enum Contig {
+    Record(usize),
+    Merged(String, Vec<usize>),
+}
+
+impl Contig {
+    fn name<'a>(&'a self, records: &'a [Extracted]) -> &'a str {
+        match self {
+            Contig::Record(i) => records[*i].2.name(),
+            Contig::Merged(name, _) => name,
+        }
+    }
+}
+
+impl Sequences {
+    // Writing output from a Sequences struct checks
+    // - whether the output location is a file or stdout
+    // - whether all contigs or a single merged contig should be written
+    // - what the name of the single merged contig should be
+    // - whether the single merged contig should have gaps of a specific size
+    //   and character, or a literal gap sequence
+    // - whether contigs should instead be merged in groups
+    // - an order to write records in, by name or sorted, instead of the
+    //   regions' order
+    // - whether to write FASTA, FASTQ, 2bit, or JSON, the quality character
+    //   for FASTQ, and whether to write ambiguous bases as N in 2bit
+    // - how many bases to write per FASTA line
+    // - whether each contig should be written to its own file in a directory
+    // - whether the output should be BGZF-compressed
+    // - whether the output should be indexed after it's written
+    // - whether to append to existing output files instead of replacing them
+    // - whether to write where each record came from to a provenance file,
+    //   and where each record is in its merged contig to a BED file
+    // - a checksum of each contig to write in its description, or to a file
+    // - whether to trim runs of N from the ends of each record first, which
+    //   leaves records of only Ns empty
+    // - a template for each record's definition line
+    pub fn write(&mut self, options: OutputOptions) -> Result<()> {
+        let index = self.check_output(&options)?;
+        self.prepare_records(&options);
+
+        // If the user didn't request a merged contig, write each contig.
+        // Otherwise, write the single merged contig, or a merged contig for
+        // each group.
+        let order = self.ordered(&options.order, options.sort);
+
+        // Write forward and reverse complemented records to their own files
+        // (e.g., out.fwd.fa and out.rev.fa for out.fa), each written as the
+        // whole output would be.
+        if options.split_strand {
+            let path = options
+                .output_location
+                .as_deref()
+                .context("splitting by strand needs an output file")?;
+            for (strand, reversed) in [("fwd", false), ("rev", true)] {
+                let indices = order
+                    .iter()
+                    .copied()
+                    .filter(|&i| self.records[i].1 == reversed)
+                    .collect();
+                let path = Self::strand_path(path, strand);
+                let contigs = self.contigs(indices, &options);
+                self.write_file(Path::new(&path), &contigs, index, &options)?;
+            }
+            return Ok(());
+        }
+
+        let contigs = self.contigs(order, &options);
+        self.write_contig_files(&contigs, index, &options)
+    }
+
+    // Check that the output options can be used together, returning whether
+    // the output can be indexed: only FASTA output can be, and only when
+    // it's uncompressed or BGZF-compressed.
+    fn check_output(&mut self, options: &OutputOptions) -> Result<bool> {
+        // 2bit and JSON files are a single structure that can't be extended
+        // by appending to them.
+        if options.append && matches!(options.format, OutputFormat::TwoBit | OutputFormat::Json) {
+            bail!("2bit and JSON output can't be appended to an existing file");
+        }
+        // 2bit, JSON, and binpacked records have no description to hold a
+        // checksum.
+        if options.checksum.is_some()
+            && options.checksum_file.is_none()
+            && matches!(
+                options.format,
+                OutputFormat::TwoBit | OutputFormat::Json | OutputFormat::BinPacked
+            )
+        {
+            bail!(
+                "2bit, JSON, and binpacked output have no descriptions for checksums; use --checksum-file"
+            );
+        }
+        // A gap sequence is usually a linker or spacer, so anything but
+        // nucleotides (including IUPAC ambiguity codes) is likely a mistake.
+        if let Some(gap_seq) = &options.gap_seq {
+            if let Some(&base) = gap_seq
+                .iter()
+                .find(|base| !b"ACGTUNRYSWKMBDHV".contains(&base.to_ascii_uppercase()))
+            {
+                if !options.force {
+                    bail!(
+                        "gap sequence contains {}, which is not a nucleotide (use --force to allow it)",
+                        char::from(base)
+                    );
+                }
+            }
+        }
+
+        let unindexed = match (options.index, options.format) {
+            (true, _) if options.plain_gzip => {
+                Some("gzip output (use BGZF, the default, to index it)")
+            }
+            (true, OutputFormat::Fastq) => Some("FASTQ output"),
+            (true, OutputFormat::TwoBit) => Some("2bit output"),
+            (true, OutputFormat::Json) => Some("JSON output"),
+            (true, OutputFormat::BinPacked) => Some("binpacked output"),
+            _ => None,
+        };
+        if let Some(unindexed) = unindexed {
+            self.diagnostics
+                .push(Diagnostic::Warning(format!("not indexing {unindexed}")));
+        }
+        Ok(options.index && unindexed.is_none())
+    }
+
+    // Prepare the extracted records for writing: check them for assembly
+    // gaps, trim Ns from their ends, and apply the header template, as
+    // requested.
+    fn prepare_records(&mut self, options: &OutputOptions) {
+        if let Some(min_gap) = options.warn_gaps {
+            let mut gap_spanning = 0;
+            for extracted in &self.records {
+                if Self::warn_gaps(extracted, min_gap, &mut self.diagnostics) {
+                    gap_spanning += 1;
+                }
+            }
+            self.gap_spanning = Some(gap_spanning);
+        }
+        if options.trim_n {
+            let mut trimmed = 0;
+            for (_, _, record) in &mut self.records {
+                trimmed += Self::trim_n(record);
+            }
+            self.trimmed = Some(trimmed);
+        }
+        if let Some(template) = &options.header_template {
+            for extracted in &mut self.records {
+                Self::apply_template(template, extracted);
+            }
+        }
+    }
+
+    // Write the contigs to the output, or to a file each in the split
+    // directory, with the files that accompany them (provenance, merged
+    // pieces, and checksums), then index the output if requested.
+    fn write_contig_files(
+        &mut self,
+        contigs: &[Contig],
+        index: bool,
+        options: &OutputOptions,
+    ) -> Result<()> {
+        if let Some(provenance) = &options.provenance {
+            self.write_provenance(provenance, contigs, options.gap().len(), options.append)?;
+        }
+        if let Some(merge_bed) = &options.merge_bed {
+            self.write_merge_bed(merge_bed, contigs, options.gap().len(), options.append)?;
+        }
+        if let Some(merge_gff) = &options.merge_gff {
+            self.write_merge_gff(merge_gff, contigs, options.gap().len(), options.append)?;
+        }
+        if let (Some(checksum), Some(path)) = (options.checksum, &options.checksum_file) {
+            self.write_checksums(path, contigs, checksum, options)?;
+        }
+
+        // Write each contig to a file in the split directory, named after
+        // the record with characters from region strings replaced.
+        if let Some(split_dir) = &options.split_dir {
+            create_dir_all(split_dir)?;
+            let extension = match (options.format, options.bgzip || options.plain_gzip) {
+                (OutputFormat::Fasta, false) => "fa",
+                (OutputFormat::Fasta, true) => "fa.gz",
+                (OutputFormat::Fastq, false) => "fq",
+                (OutputFormat::Fastq, true) => "fq.gz",
+                (OutputFormat::TwoBit, false) => "2bit",
+                (OutputFormat::TwoBit, true) => "2bit.gz",
+                (OutputFormat::Json, false) => "json",
+                (OutputFormat::Json, true) => "json.gz",
+                (OutputFormat::BinPacked, false) => "bin",
+                (OutputFormat::BinPacked, true) => "bin.gz",
+            };
+            for contig in contigs {
+                let file_name = contig.name(&self.records).replace([':', '-', '/'], "_");
+                let path = Path::new(split_dir).join(format!("{file_name}.{extension}"));
+                self.write_file(&path, slice::from_ref(contig), index, options)?;
+            }
+            return Ok(());
+        }
+
+        let (output, bgzip) = Self::open_output(options)?;
+        Self::write_output(output, bgzip, options, |output| {
+            self.write_contigs(output, contigs, options)
+        })?;
+
+        // Index the output once it's written, if it went to a file.
+        if index {
+            match &options.output_location {
+                Some(path) => Self::index_output(path, bgzip)?,
+                None => self.diagnostics.push(Diagnostic::Warning(String::from(
+                    "not indexing output written to stdout",
+                ))),
+            }
+        }
+        Ok(())
+    }
+
+    // Warn if a record has runs of at least min_gap Ns (or ns), which are
+    // likely assembly gaps, listing where they are in its sequence (1-based,
+    // after any reverse complementing). Returns whether it has any.
+    fn warn_gaps(
+        (region, _, record): &Extracted,
+        min_gap: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> bool {
+        let mut gaps = Vec::new();
+        let mut run_start = None;
+        let bases = record.sequence().as_ref();
+        for (i, base) in bases.iter().chain([&b'-']).enumerate() {
+            match (base.eq_ignore_ascii_case(&b'N'), run_start) {
+                (true, None) => run_start = Some(i),
+                (false, Some(start)) => {
+                    if i - start >= min_gap.max(1) {
+                        gaps.push(format!("{}-{i}", start + 1));
+                    }
+                    run_start = None;
+                }
+                _ => {}
+            }
+        }
+        if !gaps.is_empty() {
+            diagnostics.push(Diagnostic::Warning(format!(
+                "region {region} spans an assembly gap (runs of Ns at bases {} of its sequence)",
+                gaps.join(", ")
+            )));
+        }
+        !gaps.is_empty()
+    }
+
+    // Trim runs of N (or n) from the start and end of a record, returning
+    // how many bases were trimmed. A record of only Ns becomes empty.
+    fn trim_n(record: &mut Record) -> usize {
+        let bases = record.sequence().as_ref();
+        let is_n = |base: &u8| base.eq_ignore_ascii_case(&b'N');
+        let start = bases
+            .iter()
+            .position(|base| !is_n(base))
+            .unwrap_or(bases.len());
+        let end = bases
+            .iter()
+            .rposition(|base| !is_n(base))
+            .map_or(start, |end| end + 1);
+        let trimmed = bases.len() - (end - start);
+        if trimmed > 0 {
+            let sequence = bases[start..end].to_vec();
+            *record = Record::new(record.definition().clone(), sequence.into());
+        }
+        trimmed
+    }
+
+    // Replace a record's definition line with a header template filled in
+    // with its name, region, strand, and length. The header is split into a
+    // name and description at its first whitespace, as when reading FASTA.
+    fn apply_template(template: &HeaderTemplate, (region, reversed, record): &mut Extracted) {
+        let strand = if *reversed { '-' } else { '+' };
+        let header = template.render(record.name(), region, strand, record.sequence().len());
+        let definition = match header.split_once(char::is_whitespace) {
+            Some((name, description)) => {
+                fasta::record::Definition::new(name, Some(description.to_string()))
+            }
+            None => fasta::record::Definition::new(header, None),
+        };
+        *record = Record::new(definition, record.sequence().clone());
+    }
+
+    // Arrange records (by index, in the order to write them) into the
+    // contigs to write: each record on its own, a merged contig for each
+    // group, or a single merged contig. Empty records are skipped when
+    // merging, with a warning for each.
+    fn contigs(&mut self, order: Vec<usize>, options: &OutputOptions) -> Vec<Contig> {
+        let contigs = if let Some(pattern) = &options.group_by {
+            self.group(pattern, &order)
+        } else if !options.merge {
+            order.into_iter().map(Contig::Record).collect()
+        } else {
+            let contig_name = options
+                .contig_name
+                .clone()
+                .unwrap_or_else(|| self.regions_filename.clone());
+            vec![Contig::Merged(contig_name, order)]
+        };
+        for contig in &contigs {
+            if let Contig::Merged(name, indices) = contig {
+                for &i in indices {
+                    let record = &self.records[i].2;
+                    if record.sequence().is_empty() {
+                        self.diagnostics.push(Diagnostic::Warning(format!(
+                            "skipping empty record {} in {name}",
+                            record.name()
+                        )));
+                    }
+                }
+            }
+        }
+        contigs
+    }
+
+    // Write contigs to a file, compressed if bgzip is set or the file ends
+    // in .gz/.bgz, indexing it afterwards if requested.
+    fn write_file(
+        &self,
+        path: &Path,
+        contigs: &[Contig],
+        index: bool,
+        options: &OutputOptions,
+    ) -> Result<()> {
+        let compressed = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("gz" | "bgz")
+        );
+        let bgzip = options.bgzip || compressed;
+        let output = Box::new(Self::create_output(path, options.append)?);
+        Self::write_output(output, bgzip, options, |output| {
+            self.write_contigs(output, contigs, options)
+        })?;
+        if index {
+            Self::index_output(&path.to_string_lossy(), bgzip)?;
+        }
+        Ok(())
+    }
+
+    // The path of the output for one strand: the strand is inserted before
+    // the output's extension and any compression extension (e.g., out.fa.gz
+    // becomes out.fwd.fa.gz), or appended if it has no extension.
+    fn strand_path(path: &str, strand: &str) -> String {
+        let (rest, compression) = match path.rsplit_once('.') {
+            Some((rest, ext @ ("gz" | "bgz"))) => (rest, format!(".{ext}")),
+            _ => (path, String::new()),
+        };
+        let file_name = rest.rsplit('/').next().unwrap_or(rest);
+        match file_name.rsplit_once('.') {
+            Some((stem, ext)) if !stem.is_empty() => {
+                let dir = &rest[..rest.len() - file_name.len()];
+                format!("{dir}{stem}.{strand}.{ext}{compression}")
+            }
+            _ => format!("{rest}.{strand}{compression}"),
+        }
+    }
+
+    // Extract the regions and write their records as they're extracted,
+    // rather than all at once after extracting every region. One thread
+    // queries the regions in order and sends each record over a bounded
+    // channel to another that writes it, so reading the FASTA file overlaps
+    // formatting and writing the output, and at most PIPELINE_RECORDS
+    // records are held in memory. Records are written to a single output as
+    // FASTA or FASTQ, in the order of the regions; the output options that
+    // need every record at once (merging, splitting, reordering, sorting,
+    // and writing provenance or checksums to their own files) aren't used,
+    // and no records are kept afterwards. Progress is reported as each
+    // region is extracted.
+    pub fn extract_pipelined(
+        &mut self,
+        options: ExtractOptions,
+        output_options: OutputOptions,
+    ) -> Result<()> {
+        const PIPELINE_RECORDS: usize = 1024;
+
+        if matches!(
+            output_options.format,
+            OutputFormat::TwoBit | OutputFormat::Json
+        ) {
+            bail!("2bit and JSON output are written all at once, so they can't be pipelined");
+        }
+        self.extract_or_prepare(&options, false)?;
+        let (sender, receiver) = mpsc::sync_channel::<Extracted>(PIPELINE_RECORDS);
+
+        let reader = self
+            .reader
+            .as_mut()
+            .context("the FASTA file was not opened")?;
+        let regions = &self.regions;
+        let blocks = &self.blocks;
+        let diagnostics = &mut self.diagnostics;
+        thread::scope(|scope| {
+            // The output is opened on the writing thread, since stdout's
+            // lock can't be sent between threads.
+            let writer = scope.spawn(|| -> Result<Vec<Diagnostic>> {
+                let mut diagnostics = Vec::new();
+                let (output, bgzip) = Self::open_output(&output_options)?;
+                let line_width = match output_options.line_width {
+                    0 => usize::MAX,
+                    line_width => line_width,
+                };
+                Self::write_output(output, bgzip, &output_options, |output| {
+                    for mut extracted in receiver {
+                        if let Some(min_gap) = output_options.warn_gaps {
+                            Self::warn_gaps(&extracted, min_gap, &mut diagnostics);
+                        }
+                        if output_options.trim_n {
+                            Self::trim_n(&mut extracted.2);
+                        }
+                        if let Some(template) = &output_options.header_template {
+                            Self::apply_template(template, &mut extracted);
+                        }
+                        let record = extracted.2;
+                        let checksum = output_options.checksum.map(|checksum| {
+                            let digest = checksum.digest([record.sequence().as_ref()]);
+                            format!("{}={digest}", checksum.name())
+                        });
+                        Self::write_record(output, &record, checksum, line_width, &output_options)?;
+                    }
+                    Ok(())
+                })?;
+                if output_options.index {
+                    match &output_options.output_location {
+                        Some(path) => Self::index_output(path, bgzip)?,
+                        None => diagnostics.push(Diagnostic::Warning(String::from(
+                            "not indexing output written to stdout",
+                        ))),
+                    }
+                }
+                Ok(diagnostics)
+            });
+
+            // If writing fails, the receiver is dropped and sending fails, so
+            // extraction stops and the writer's error is reported.
+            let mut extracted = Ok(());
+            let total = regions.len();
+            if let Some(progress) = &options.progress {
+                progress(0, total);
+            }
+            for (i, region) in regions.iter().enumerate() {
+                let region_blocks = blocks.get(&Self::block_key(&region.0, &region.2));
+                let record = match Self::extract_region(
+                    reader,
+                    None,
+                    region,
+                    region_blocks.map(Vec::as_slice),
+                    &options,
+                    diagnostics,
+                ) {
+                    Ok(record) => record,
+                    Err(e) => {
+                        extracted = Err(e);
+                        break;
+                    }
+                };
+                if let Some(progress) = &options.progress {
+                    progress(i + 1, total);
+                }
+                if let Some(filter) = &options.filter {
+                    if !filter.matches(record.sequence().as_ref()) {
+                        continue;
+                    }
+                }
+                if sender.send((region.0.clone(), region.1, record)).is_err() {
+                    break;
+                }
+            }
+            drop(sender);
+            let written = writer
+                .join()
+                .unwrap_or_else(|_| bail!("the writing thread panicked"))?;
+            diagnostics.extend(written);
+            extracted
+        })
+    }
+
+    // Open the output file, or stdout, returning whether output should be
+    // BGZF-compressed: if requested, or if the output file ends in .gz/.bgz.
+    fn open_output(options: &OutputOptions) -> Result<(Box<dyn Write>, bool)> {
+        Ok(match &options.output_location {
+            Some(path) => {
+                let compressed = matches!(
+                    Path::new(path).extension().and_then(|ext| ext.to_str()),
+                    Some("gz" | "bgz")
+                );
+                let output = Self::create_output(path, options.append)?;
+                (Box::new(output), options.bgzip || compressed)
+            }
+            None => (Box::new(io::stdout().lock()), options.bgzip),
+        })
+    }
+
+    // Return each extracted record in each contig, by its index, with the
+    // contig's name and the record's 0-based offset in the contig (nonzero
+    // only when merging), tracking the records and gaps before it. Empty
+    // records are skipped, as they are when merging.
+    fn record_offsets<'a>(
+        &'a self,
+        contigs: &'a [Contig],
+        gap_size: usize,
+    ) -> Vec<(&'a str, usize, usize)> {
+        let mut offsets = Vec::new();
+        for contig in contigs {
+            let name = contig.name(&self.records);
+            let indices = match contig {
+                Contig::Record(i) => slice::from_ref(i),
+                Contig::Merged(_, indices) => &indices[..],
+            };
+            let mut offset = 0;
+            for &i in indices {
+                let length = self.records[i].2.sequence().len();
+                if length == 0 && matches!(contig, Contig::Merged(..)) {
+                    continue;
+                }
+                offsets.push((name, i, offset));
+                offset += length + gap_size;
+            }
+        }
+        offsets
+    }
+
+    // Write a line for each extracted record in each contig with the
+    // record's contig name, source contig, 1-based genomic start and end,
+    // strand, and 0-based offset in the contig.
+    fn write_provenance(
+        &self,
+        path: &str,
+        contigs: &[Contig],
+        gap_size: usize,
+        append: bool,
+    ) -> Result<()> {
+        let mut output = Self::create_output(path, append)
+            .with_context(|| format!("could not create {path}"))?;
+        for (name, i, offset) in self.record_offsets(contigs, gap_size) {
+            let (region, reversed, _) = &self.records[i];
+            let interval = region.interval();
+            let start = interval.start().map(usize::from).unwrap_or(1);
+            let end = match interval.end() {
+                Some(end) => usize::from(end),
+                None => self
+                    .reader
+                    .iter()
+                    .flat_map(|reader| reader.index())
+                    .find(|record| record.name() == region.name())
+                    .map_or(0, |record| record.length() as usize),
+            };
+            let strand = if *reversed { '-' } else { '+' };
+            writeln!(
+                output,
+                "{name}\t{}\t{start}\t{end}\t{strand}\t{offset}",
+                region.name()
+            )?;
+        }
+        Ok(())
+    }
+
+    // Write a BED line for each record in each merged contig with the
+    // contig's name, the record's 0-based start and exclusive end in the
+    // contig, the record's name, a score of 0, and the record's strand, so
+    // features of the merged contig can be traced to their source regions.
+    fn write_merge_bed(
+        &self,
+        path: &str,
+        contigs: &[Contig],
+        gap_size: usize,
+        append: bool,
+    ) -> Result<()> {
+        let mut output = Self::create_output(path, append)
+            .with_context(|| format!("could not create {path}"))?;
+        for (name, i, offset) in self.record_offsets(contigs, gap_size) {
+            let (_, reversed, record) = &self.records[i];
+            let end = offset + record.sequence().len();
+            let strand = if *reversed { '-' } else { '+' };
+            writeln!(
+                output,
+                "{name}\t{offset}\t{end}\t{}\t0\t{strand}",
+                record.name()
+            )?;
+        }
+        Ok(())
+    }
+
+    // Write a GFF3 file of where each record is in its merged contig, for
+    // tools that read features of the merged contig: a sequence-region
+    // directive for each merged contig with its length, then a region
+    // feature for each record with its 1-based start and end in the contig
+    // (accounting for gaps), its strand, and its name and source region as
+    // attributes. The version directive is only written at the start of a
+    // file, so appending keeps it a single GFF3 file.
+    fn write_merge_gff(
+        &self,
+        path: &str,
+        contigs: &[Contig],
+        gap_size: usize,
+        append: bool,
+    ) -> Result<()> {
+        let output = Self::create_output(path, append)
+            .with_context(|| format!("could not create {path}"))?;
+        let empty = output.metadata()?.len() == 0;
+        let mut output = BufWriter::new(output);
+        let mut writer = gff::Writer::new(&mut output);
+        if empty {
+            writer.write_directive(&gff::Directive::GffVersion(Default::default()))?;
+        }
+
+        let offsets = self.record_offsets(contigs, gap_size);
+        for (i, &(name, _, _)) in offsets.iter().enumerate() {
+            if i > 0 && offsets[i - 1].0 == name {
+                continue;
+            }
+            let length = offsets[i..]
+                .iter()
+                .take_while(|(contig, _, _)| *contig == name)
+                .last()
+                .map_or(0, |&(_, last, offset)| {
+                    offset + self.records[last].2.sequence().len()
+                });
+            let region =
+                gff::directive::SequenceRegion::new(name.to_string(), 1, i32::try_from(length)?);
+            writer.write_directive(&gff::Directive::SequenceRegion(region))?;
+        }
+        for (name, i, offset) in offsets {
+            let (region, reversed, record) = &self.records[i];
+            let mut attributes = gff::record::Attributes::default();
+            attributes.insert(String::from("Name"), record.name().into());
+            attributes.insert(String::from("region"), region.to_string().into());
+            let feature = gff::Record::builder()
+                .set_reference_sequence_name(name.to_string())
+                .set_source(String::from("extract"))
+                .set_type(String::from("region"))
+                .set_start(Position::try_from(offset + 1)?)
+                .set_end(Position::try_from(offset + record.sequence().len())?)
+                .set_strand(if *reversed {
+                    gff::record::Strand::Reverse
+                } else {
+                    gff::record::Strand::Forward
+                })
+                .set_attributes(attributes)
+                .build();
+            writer.write_record(&feature)?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    // Write a line for each contig with its name and checksum.
+    fn write_checksums(
+        &self,
+        path: &str,
+        contigs: &[Contig],
+        checksum: Checksum,
+        options: &OutputOptions,
+    ) -> Result<()> {
+        let mut output = Self::create_output(path, options.append)
+            .with_context(|| format!("could not create {path}"))?;
+        for contig in contigs {
+            let digest = self.contig_checksum(contig, checksum, options);
+            writeln!(output, "{}\t{digest}", contig.name(&self.records))?;
+        }
+        Ok(())
+    }
+
+    // Return the checksum of a contig's sequence as it's written: a record,
+    // or the non-empty records of a merged contig with the gaps between
+    // them.
+    fn contig_checksum(
+        &self,
+        contig: &Contig,
+        checksum: Checksum,
+        options: &OutputOptions,
+    ) -> String {
+        let indices = match contig {
+            Contig::Record(i) => return checksum.digest([self.records[*i].2.sequence().as_ref()]),
+            Contig::Merged(_, indices) => indices,
+        };
+        let records: Vec<&Record> = indices
+            .iter()
+            .map(|&i| &self.records[i].2)
+            .filter(|record| !record.sequence().is_empty())
+            .collect();
+        let gap = options.gap();
+        checksum.digest(records.iter().enumerate().flat_map(|(i, record)| {
+            let gap = if i + 1 < records.len() { &gap[..] } else { &[] };
+            [record.sequence().as_ref(), gap]
+        }))
+    }
+
+    // Create an output file, or open it for appending (creating it if it
+    // doesn't exist).
+    fn create_output<P: AsRef<Path>>(path: P, append: bool) -> io::Result<File> {
+        if append {
+            OpenOptions::new().create(true).append(true).open(path)
+        } else {
+            File::create(path)
+        }
+    }
+
+    // Write contigs to the output, optionally BGZF-compressed, or compressed
+    // with plain gzip, which is smaller to write but can't be indexed or
+    // queried by region. The (compressed) output is buffered, since records
+    // are written in many small pieces, and flushed once they're written so
+    // errors aren't lost when the buffer is dropped.
+    fn write_output(
+        output: Box<dyn Write>,
+        bgzip: bool,
+        options: &OutputOptions,
+        write: impl FnOnce(&mut dyn Write) -> Result<()>,
+    ) -> Result<()> {
+        let mut output = BufWriter::with_capacity(options.buffer_size, output);
+        if options.plain_gzip {
+            let mut writer = GzEncoder::new(&mut output, Compression::default());
+            write(&mut writer)?;
+            writer.finish()?;
+        } else if bgzip {
+            let mut writer = bgzf::Writer::new(&mut output);
+            write(&mut writer)?;
+            writer.finish()?;
+        } else {
+            write(&mut output)?;
+        }
+        output.flush()?;
+        Ok(())
+    }
+
+    // Write contigs to the output as FASTA, FASTQ, or 2bit, with gaps
+    // between the records of merged contigs.
+    fn write_contigs(
+        &self,
+        output: &mut dyn Write,
+        contigs: &[Contig],
+        options: &OutputOptions,
+    ) -> Result<()> {
+        // 2bit files start with an index of every sequence's offset, and
+        // JSON output is a single array, so the contigs are written all at
+        // once, with merged contigs concatenated in memory.
+        match options.format {
+            OutputFormat::TwoBit => {
+                let sequences = self.contig_sequences(contigs, options);
+                twobit::write(output, &sequences, options.force)?;
+                return Ok(());
+            }
+            OutputFormat::Json => return self.write_json(output, contigs, options),
+            OutputFormat::Fasta | OutputFormat::Fastq | OutputFormat::BinPacked => {}
+        }
+
+        // A line width of 0 writes each sequence on a single line.
+        let line_width = if options.line_width == 0 {
+            usize::MAX
+        } else {
+            options.line_width
+        };
+        for contig in contigs {
+            // Checksums go in each contig's description unless they're
+            // written to their own file.
+            let checksum = match (options.checksum, &options.checksum_file) {
+                (Some(checksum), None) => Some(format!(
+                    "{}={}",
+                    checksum.name(),
+                    self.contig_checksum(contig, checksum, options)
+                )),
+                _ => None,
+            };
+            match contig {
+                Contig::Record(i) => {
+                    let (_, _, record) = &self.records[*i];
+                    Self::write_record(output, record, checksum, line_width, options)?;
+                }
+                Contig::Merged(name, indices) => {
+                    self.write_merged(output, name, indices, checksum, line_width, options)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Write a record as FASTA or FASTQ, with a checksum in its description,
+    // if given.
+    fn write_record(
+        output: &mut dyn Write,
+        record: &Record,
+        checksum: Option<String>,
+        line_width: usize,
+        options: &OutputOptions,
+    ) -> Result<()> {
+        match options.format {
+            OutputFormat::Fasta => {
+                let record = match checksum {
+                    Some(checksum) => {
+                        let description = match record.description() {
+                            Some(description) => format!("{description} {checksum}"),
+                            None => checksum,
+                        };
+                        let definition =
+                            fasta::record::Definition::new(record.name(), Some(description));
+                        Cow::Owned(Record::new(definition, record.sequence().clone()))
+                    }
+                    None => Cow::Borrowed(record),
+                };
+                fasta::writer::Builder::default()
+                    .set_line_base_count(line_width)
+                    .build_with_writer(&mut *output)
+                    .write_record(&record)?;
+            }
+            OutputFormat::Fastq => {
+                // FASTQ records carry a quality score per base, so use the
+                // same quality character for every base of the sequence.
+                let definition =
+                    fastq::record::Definition::new(record.name(), checksum.unwrap_or_default());
+                let sequence = record.sequence().as_ref();
+                let quality_scores = vec![options.quality; sequence.len()];
+                let record = fastq::Record::new(definition, sequence, quality_scores);
+                fastq::Writer::new(&mut *output).write_record(&record)?;
+            }
+            OutputFormat::BinPacked => binpacked::write_record(output, record.sequence().as_ref())?,
+            OutputFormat::TwoBit | OutputFormat::Json => {
+                unreachable!("2bit and JSON output are written all at once")
+            }
+        }
+        Ok(())
+    }
+
+    // Write records merged into a single contig with the given name. Each
+    // record's sequence (and the gap after it, except the last) is written
+    // straight to the output rather than concatenated in memory, with FASTA
+    // lines wrapping across records. The FASTA description lists the
+    // records' descriptions (e.g., from annotation). Empty records (e.g.,
+    // from a region past the end of its contig) are skipped, so they don't
+    // add gaps.
+    fn write_merged(
+        &self,
+        output: &mut dyn Write,
+        name: &str,
+        indices: &[usize],
+        checksum: Option<String>,
+        line_width: usize,
+        options: &OutputOptions,
+    ) -> Result<()> {
+        let records = self.merged_records(indices);
+        let gap = options.gap();
+        let pieces = records.iter().enumerate().flat_map(|(i, record)| {
+            let gap = if i + 1 < records.len() { &gap[..] } else { &[] };
+            [record.sequence().as_ref(), gap]
+        });
+
+        match options.format {
+            OutputFormat::Fasta => {
+                let descriptions: Vec<&str> = records
+                    .iter()
+                    .filter_map(|record| record.description())
+                    .collect();
+                let description = match (descriptions.is_empty(), checksum) {
+                    (true, checksum) => checksum,
+                    (false, Some(checksum)) => {
+                        Some(format!("{} {checksum}", descriptions.join("; ")))
+                    }
+                    (false, None) => Some(descriptions.join("; ")),
+                };
+                let definition = fasta::record::Definition::new(name, description);
+                writeln!(output, "{definition}")?;
+
+                let mut column = 0;
+                for mut bases in pieces {
+                    while !bases.is_empty() {
+                        let n = (line_width - column).min(bases.len());
+                        output.write_all(&bases[..n])?;
+                        bases = &bases[n..];
+                        column += n;
+                        if column == line_width {
+                            writeln!(output)?;
+                            column = 0;
+                        }
+                    }
+                }
+                if column > 0 {
+                    writeln!(output)?;
+                }
+            }
+            OutputFormat::Fastq => {
+                match checksum {
+                    Some(checksum) => writeln!(output, "@{name} {checksum}")?,
+                    None => writeln!(output, "@{name}")?,
+                }
+                let mut length = 0;
+                for bases in pieces {
+                    output.write_all(bases)?;
+                    length += bases.len() as u64;
+                }
+                writeln!(output, "\n+")?;
+                io::copy(&mut io::repeat(options.quality).take(length), output)?;
+                writeln!(output)?;
+            }
+            OutputFormat::BinPacked => {
+                let length = pieces.clone().map(<[u8]>::len).sum();
+                binpacked::write_length(output, length)?;
+                for bases in pieces {
+                    output.write_all(bases)?;
+                }
+            }
+            OutputFormat::TwoBit | OutputFormat::Json => {
+                unreachable!("2bit and JSON output are written all at once")
+            }
+        }
+        Ok(())
+    }
+
+    // Return the name and sequence of each contig, concatenating the records
+    // of merged contigs.
+    fn contig_sequences<'a>(
+        &'a self,
+        contigs: &'a [Contig],
+        options: &OutputOptions,
+    ) -> Vec<(&'a str, Cow<'a, [u8]>)> {
+        contigs
+            .iter()
+            .map(|contig| match contig {
+                Contig::Record(i) => {
+                    let (_, _, record) = &self.records[*i];
+                    (record.name(), Cow::Borrowed(record.sequence().as_ref()))
+                }
+                Contig::Merged(name, indices) => {
+                    let sequence = self.merged_sequence(indices, &options.gap());
+                    (name.as_str(), Cow::Owned(sequence))
+                }
+            })
+            .collect()
+    }
+
+    // Write contigs as a JSON array of objects with each record's name,
+    // region, strand, sequence, and length, on one line. A single merged
+    // contig (rather than groups) is written as a single object.
+    fn write_json(
+        &self,
+        output: &mut dyn Write,
+        contigs: &[Contig],
+        options: &OutputOptions,
+    ) -> Result<()> {
+        let sequences = self.contig_sequences(contigs, options);
+        let records: Vec<JsonRecord> = contigs
+            .iter()
+            .zip(&sequences)
+            .map(|(contig, (name, sequence))| {
+                let (region, strand) = match contig {
+                    Contig::Record(i) => {
+                        let (region, reversed, _) = &self.records[*i];
+                        (
+                            Some(region.to_string()),
+                            Some(if *reversed { '-' } else { '+' }),
+                        )
+                    }
+                    Contig::Merged(..) => (None, None),
+                };
+                JsonRecord {
+                    name,
+                    region,
+                    strand,
+                    sequence: String::from_utf8_lossy(sequence),
+                    length: sequence.len(),
+                }
+            })
+            .collect();
+        match (&records[..], options.merge && options.group_by.is_none()) {
+            ([record], true) => serde_json::to_writer(&mut *output, record)?,
+            _ => serde_json::to_writer(&mut *output, &records)?,
+        }
+        writeln!(output)?;
+        Ok(())
+    }
+
+    // Return the non-empty records to merge into a contig.
+    fn merged_records(&self, indices: &[usize]) -> Vec<&Record> {
+        indices
+            .iter()
+            .map(|&i| &self.records[i].2)
+            .filter(|record| !record.sequence().is_empty())
+            .collect()
+    }
+
+    // Concatenate the records merged into a contig, with gaps between them.
+    fn merged_sequence(&self, indices: &[usize], gap: &[u8]) -> Vec<u8> {
+        let records = self.merged_records(indices);
+        let mut sequence = Vec::new();
+        for (i, record) in records.iter().enumerate() {
+            if i > 0 {
+                sequence.extend_from_slice(gap);
+            }
+            sequence.extend_from_slice(record.sequence().as_ref());
+        }
+        sequence
+    }
+
+    // Grouping the extracted records merges the records whose names share a
+    // key, in the order each key first appears, into a contig named by the
+    // key. The key is the pattern's first capture group (or the whole match,
+    // without groups) in the record's name; records whose names don't match
+    // are grouped by their whole name.
+    fn group(&self, pattern: &Regex, order: &[usize]) -> Vec<Contig> {
+        let mut keys: HashMap<&str, usize> = HashMap::new();
+        let mut groups: Vec<Contig> = Vec::new();
+        for &i in order {
+            let name = self.records[i].2.name();
+            let key = match pattern.captures(name) {
+                Some(captures) => captures
+                    .get(1)
+                    .or_else(|| captures.get(0))
+                    .map_or(name, |key| key.as_str()),
+                None => name,
+            };
+            let group = *keys.entry(key).or_insert_with(|| {
+                groups.push(Contig::Merged(key.to_string(), Vec::new()));
+                groups.len() - 1
+            });
+            if let Contig::Merged(_, indices) = &mut groups[group] {
+                indices.push(i);
+            }
+        }
+        groups
+    }
+
+    // Return the indices of the records in the order to write them: sorted,
+    // if requested, or by the rank of their names in an order file, if one
+    // was given, with records that compare equal in the order they were
+    // extracted. Records whose names aren't in the order file are written
+    // last, with a warning.
+    fn ordered(&mut self, order: &HashMap<String, usize>, sort: Option<SortOrder>) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.records.len()).collect();
+        if let Some(sort) = sort {
+            self.sort(&mut indices, sort);
+            return indices;
+        }
+        if order.is_empty() {
+            return indices;
+        }
+        indices.sort_by_key(|&i| {
+            order
+                .get(self.records[i].2.name())
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+        let unordered = indices
+            .iter()
+            .filter(|&&i| !order.contains_key(self.records[i].2.name()))
+            .count();
+        if unordered > 0 {
+            self.diagnostics.push(Diagnostic::Warning(format!(
+                "writing {unordered} records not in the order file last"
+            )));
+        }
+        indices
+    }
+
+    // Sort record indices by name, coordinate, or descending length. Records
+    // are sorted by coordinate by their regions (as extracted, e.g., after
+    // flanking), with contigs in the order of the FASTA index.
+    fn sort(&self, indices: &mut [usize], sort: SortOrder) {
+        match sort {
+            SortOrder::Name => indices.sort_by_key(|&i| self.records[i].2.name()),
+            SortOrder::Coord => {
+                let contigs: HashMap<&str, usize> = self
+                    .reader
+                    .iter()
+                    .flat_map(|reader| reader.index())
+                    .enumerate()
+                    .map(|(rank, record)| (record.name(), rank))
+                    .collect();
+                indices.sort_by_key(|&i| {
+                    let region = &self.records[i].0;
+                    let interval = region.interval();
+                    (
+                        contigs.get(region.name()).copied().unwrap_or(usize::MAX),
+                        interval.start().map_or(1, usize::from),
+                        interval.end().map_or(usize::MAX, usize::from),
+                    )
+                });
+            }
+            SortOrder::Length => {
+                indices.sort_by_key(|&i| Reverse(self.records[i].2.sequence().len()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::sequences::{tests::write_fasta, Coordinates};
+
+    // Options for writing FASTA to a file, without merging, compressing, or
+    // any other files.
+    fn output_options(path: &Path) -> OutputOptions {
+        OutputOptions {
+            output_location: Some(path.to_string_lossy().into_owned()),
+            merge: false,
+            contig_name: None,
+            gap_size: 0,
+            gap_char: b'N',
+            gap_seq: None,
+            format: OutputFormat::Fasta,
+            quality: b'I',
+            line_width: 60,
+            split_dir: None,
+            bgzip: false,
+            plain_gzip: false,
+            index: false,
+            group_by: None,
+            provenance: None,
+            merge_bed: None,
+            merge_gff: None,
+            force: false,
+            append: false,
+            checksum: None,
+            checksum_file: None,
+            order: HashMap::new(),
+            sort: None,
+            split_strand: false,
+            trim_n: false,
+            warn_gaps: None,
+            header_template: None,
+            buffer_size: 8192,
+        }
+    }
+
+    // Extract SAMtools-style regions from a FASTA file and write them,
+    // returning the warnings and notes.
+    fn write(fasta: &str, regions: &[&str], options: OutputOptions) -> Result<Vec<Diagnostic>> {
+        let regions = regions
+            .iter()
+            .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+            .collect();
+        let mut sequences = Sequences::from_regions(fasta, regions)?;
+        sequences.extract(ExtractOptions::default())?;
+        sequences.write(options)?;
+        Ok(sequences.take_diagnostics())
+    }
+
+    #[test]
+    fn empty_records_are_written_in_every_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGT\n>gap\nNNNN\n");
+        for format in [
+            OutputFormat::Fasta,
+            OutputFormat::Fastq,
+            OutputFormat::TwoBit,
+            OutputFormat::Json,
+            OutputFormat::BinPacked,
+        ] {
+            for merge in [false, true] {
+                let path = dir.path().join("out");
+                let options = OutputOptions {
+                    format,
+                    merge,
+                    trim_n: true,
+                    provenance: Some(dir.path().join("out.tsv").to_string_lossy().into()),
+                    merge_bed: merge.then(|| dir.path().join("out.bed").to_string_lossy().into()),
+                    merge_gff: merge.then(|| dir.path().join("out.gff").to_string_lossy().into()),
+                    checksum: Some(Checksum::Md5),
+                    checksum_file: Some(dir.path().join("out.md5").to_string_lossy().into()),
+                    ..output_options(&path)
+                };
+                write(&fasta, &["gap"], options).unwrap();
+            }
+        }
+
+        let path = dir.path().join("out.fa");
+        let options = OutputOptions {
+            trim_n: true,
+            ..output_options(&path)
+        };
+        write(&fasta, &["gap"], options).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">gap\n");
+    }
+
+    #[test]
+    fn merging_skips_empty_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGT\n>gap\nNNNN\n");
+        let path = dir.path().join("out.fa");
+        let options = OutputOptions {
+            merge: true,
+            gap_size: 1,
+            gap_char: b'-',
+            trim_n: true,
+            ..output_options(&path)
+        };
+        let diagnostics = write(&fasta, &["chr1:1-2", "gap", "chr1:3-4"], options).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), ">merged\nAC-GT\n");
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::Warning(String::from(
+                "skipping empty record gap in merged"
+            ))]
+        );
+    }
+
+    #[test]
+    fn binpacked_records_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGTacgtAAC\n>gap\nNNNN\n");
+        let read = |path: &Path| {
+            let mut reader = &fs::read(path).unwrap()[..];
+            let mut records = Vec::new();
+            while let Some(record) = binpacked::read_record(&mut reader).unwrap() {
+                records.push(String::from_utf8(record).unwrap());
+            }
+            records
+        };
+        let regions = ["chr1:1-6", "-chr1:9-11", "gap", "chr1"];
+
+        let path = dir.path().join("out.bin");
+        let options = OutputOptions {
+            format: OutputFormat::BinPacked,
+            trim_n: true,
+            ..output_options(&path)
+        };
+        write(&fasta, &regions, options).unwrap();
+        assert_eq!(read(&path), ["ACGTac", "GTT", "", "ACGTacgtAAC"]);
+
+        let options = OutputOptions {
+            format: OutputFormat::BinPacked,
+            merge: true,
+            gap_size: 2,
+            ..output_options(&path)
+        };
+        write(&fasta, &regions[..2], options).unwrap();
+        assert_eq!(read(&path), ["ACGTacNNGTT"]);
+    }
+
+    #[test]
+    fn identical_sequences_have_identical_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGTACGT\n>chr2\nacgt\n");
+        let checksums = dir.path().join("out.md5");
+        let options = |merge| OutputOptions {
+            merge,
+            gap_size: 2,
+            checksum: Some(Checksum::Md5),
+            checksum_file: Some(checksums.to_string_lossy().into()),
+            ..output_options(&dir.path().join("out.fa"))
+        };
+
+        // The same bases from different regions, in either case.
+        write(&fasta, &["chr1:1-4", "chr1:5-8", "chr2"], options(false)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&checksums).unwrap(),
+            "chr1:1-4\tf1f8f4bf413b16ad135722aa4591043e\n\
+             chr1:5-8\tf1f8f4bf413b16ad135722aa4591043e\n\
+             chr2\tf1f8f4bf413b16ad135722aa4591043e\n"
+        );
+
+        // A merged contig's checksum includes the gap between its records,
+        // as in ACGTNNACGT.
+        write(&fasta, &["chr1:1-4", "chr2"], options(true)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&checksums).unwrap(),
+            "merged\t1c4ab0b3062cfd287a4c8b1d77ac5bd8\n"
+        );
+    }
+
+    #[test]
+    fn pipelined_extraction_reports_progress() {
+        use std::sync::{Arc, Mutex};
+
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGTACGT\n");
+        let path = dir.path().join("out.fa");
+        let regions = ["chr1:1-4", "chr1:5-8", "-chr1"]
+            .iter()
+            .map(|region| Sequences::parse_region(region, Coordinates::OneBased).unwrap())
+            .collect();
+        let ticks = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&ticks);
+        let options = ExtractOptions {
+            progress: Some(Box::new(move |done, total| {
+                reported.lock().unwrap().push((done, total));
+            })),
+            ..Default::default()
+        };
+        let mut sequences = Sequences::from_regions(&fasta, regions).unwrap();
+        sequences
+            .extract_pipelined(options, output_options(&path))
+            .unwrap();
+        assert_eq!(*ticks.lock().unwrap(), [(0, 3), (1, 3), (2, 3), (3, 3)]);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            ">chr1:1-4\nACGT\n>chr1:5-8\nACGT\n>chr1\nACGTACGT\n"
+        );
+    }
+}
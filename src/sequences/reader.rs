@@ -0,0 +1,413 @@
+// Opening FASTA files for querying, with their indexes, which are built
+// (and kept current) as needed.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Cursor, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use noodles::{
+    bgzf,
+    fasta::{self, fai, io::BufReadSeek, IndexedReader},
+};
+use rayon::ThreadPoolBuilder;
+
+#[cfg(feature = "remote")]
+use crate::remote;
+use crate::{index, multi, Diagnostic};
+
+use super::{ExtractOptions, Sequences};
+
+// Whether a FASTA file is an HTTP(S) URL rather than a path.
+pub(super) fn is_url(fasta_file: &str) -> bool {
+    fasta_file.starts_with("http://") || fasta_file.starts_with("https://")
+}
+
+impl Sequences {
+    // Index a FASTA file, bgzipped if it ends in .gz/.bgz, replacing any
+    // existing indexes.
+    pub fn index_fasta(fasta_file: &str) -> Result<()> {
+        let compressed = matches!(
+            Path::new(fasta_file)
+                .extension()
+                .and_then(|ext| ext.to_str()),
+            Some("gz" | "bgz")
+        );
+        Self::index_output(fasta_file, compressed)
+    }
+
+    // Return the name and length of each contig in a FASTA file's index,
+    // building the index if it doesn't exist.
+    pub fn contig_lengths(
+        fasta_file: &str,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<(String, u64)>> {
+        let reader = Self::get_reader(fasta_file, false, None, None, 1, diagnostics)?;
+        Ok(reader
+            .index()
+            .iter()
+            .map(|record| (record.name().to_string(), record.length()))
+            .collect())
+    }
+
+    // Write a .fai index (and a .gzi index, if compressed) next to a FASTA
+    // file that was just written, replacing any existing indexes.
+    pub(super) fn index_output(fasta_file: &str, compressed: bool) -> Result<()> {
+        if compressed {
+            let index = index::build_gzi(BufReader::new(File::open(fasta_file)?))?;
+            index::write_gzi(File::create(format!("{fasta_file}.gzi"))?, &index)?;
+        }
+        Self::write_fai(fasta_file, compressed, &format!("{fasta_file}.fai"), 1)?;
+        Ok(())
+    }
+
+    // Open the FASTA file with its index or, without one, by reading the
+    // contigs of the regions into memory, then resolve contigs given by
+    // digest. An indexed FASTA file stays open if regions are extracted
+    // again.
+    pub(super) fn open_reader(&mut self, options: &ExtractOptions) -> Result<()> {
+        let reader = if let (Some(reader), false) = (self.reader.take(), options.no_index) {
+            reader
+        } else if options.no_index {
+            if let Some((region, _, _)) = self
+                .regions
+                .iter()
+                .find(|(region, _, _)| Self::digest_of(region.name()).is_some())
+            {
+                bail!(
+                    "contigs given by digest, like {}, need the FASTA index or --contig-md5",
+                    region.name()
+                );
+            }
+            // A region on a pattern is read from a contig named like the
+            // whole region or the pattern, if there is one.
+            let mut names: Vec<String> = Vec::new();
+            for (region, _, _) in &self.regions {
+                let aliases = Self::contig_aliases(region.name(), options);
+                let pattern =
+                    Self::split_pattern(region.name()).map(|(pattern, _)| pattern.to_string());
+                for name in [region.name().to_string()]
+                    .into_iter()
+                    .chain(pattern)
+                    .chain(aliases)
+                {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            let reader = Self::scan_reader(&self.fasta_file, &names)?;
+
+            // Without the index, a contig named like a pattern can be read
+            // by its name, but a pattern can't be matched against every
+            // contig.
+            let scanned = |name: &str| reader.index().iter().any(|record| record.name() == name);
+            if let Some((region, _, _)) = self.regions.iter().find(|(region, _, _)| {
+                Self::split_pattern(region.name())
+                    .is_some_and(|(pattern, _)| !scanned(region.name()) && !scanned(pattern))
+            }) {
+                bail!(
+                    "contig patterns like {} need the FASTA index",
+                    region.name()
+                );
+            }
+            reader
+        } else {
+            Self::open_indexed(&self.fasta_file, options, &mut self.diagnostics)?
+        };
+        let reader = self.reader.insert(reader);
+        Self::digest_contigs(
+            &mut self.regions,
+            &mut self.blocks,
+            reader,
+            &mut self.digests,
+        )
+    }
+
+    // Open the FASTA file with its index, joined with any extra FASTA files
+    // (e.g., alt contigs), each with its own index, so a query is routed to
+    // the file its contig is in.
+    pub(super) fn open_indexed(
+        fasta_file: &str,
+        options: &ExtractOptions,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
+        let reader = Self::get_reader(
+            fasta_file,
+            options.strict,
+            options.fai.as_deref(),
+            options.fai_out.as_deref(),
+            options.threads,
+            diagnostics,
+        )?;
+        if options.extra_fasta.is_empty() {
+            return Ok(reader);
+        }
+        let mut files = vec![(fasta_file, reader)];
+        for extra_fasta in &options.extra_fasta {
+            let reader = Self::get_reader(
+                extra_fasta,
+                options.strict,
+                None,
+                None,
+                options.threads,
+                diagnostics,
+            )?;
+            files.push((extra_fasta, reader));
+        }
+        multi::join(files)
+    }
+
+    // Return an IndexedReader, creating an index if one does not exist.
+    // The index is read from the given .fai path, if any, instead of next
+    // to the FASTA file, and a newly built index is written to the given
+    // output path, if any, instead of where it's read from (e.g., for a
+    // reference in a read-only directory). A plaintext FASTA file is indexed
+    // with the given number of threads. Bgzipped references (.gz/.bgz)
+    // additionally need a .gzi index, which is likewise built and written
+    // next to the file if it is missing.
+    fn get_reader(
+        fasta_file: &str,
+        strict: bool,
+        fai: Option<&str>,
+        fai_out: Option<&str>,
+        threads: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
+        // A FASTA file of "-" is read from stdin. Querying needs random
+        // access, so the whole stream is buffered and indexed in memory.
+        if fasta_file == "-" {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            let index = index::index_reader(&data[..])?;
+            let reader: Box<dyn BufReadSeek> = Box::new(Cursor::new(data));
+            return Ok(fasta::indexed_reader::Builder::default()
+                .set_index(index)
+                .build_from_reader(reader)?);
+        }
+
+        // A FASTA file at a URL is read with range requests, with the index
+        // next to it.
+        if is_url(fasta_file) {
+            #[cfg(feature = "remote")]
+            return remote::open(fasta_file);
+            #[cfg(not(feature = "remote"))]
+            bail!("reading FASTA files from URLs needs the remote feature: {fasta_file}");
+        }
+
+        let compressed = matches!(
+            Path::new(fasta_file)
+                .extension()
+                .and_then(|ext| ext.to_str()),
+            Some("gz" | "bgz")
+        );
+        let gzi = format!("{fasta_file}.gzi");
+        if compressed && !Self::is_current(fasta_file, &gzi, strict, diagnostics)? {
+            let index = index::build_gzi(BufReader::new(File::open(fasta_file)?))?;
+            index::write_gzi(File::create(&gzi)?, &index)?;
+        }
+
+        let fai = fai.map_or_else(|| format!("{fasta_file}.fai"), String::from);
+        // An index written to --fai-out by an earlier run is reused while it's
+        // current, so it's only rebuilt when it's missing or stale.
+        let fai_out = fai_out.unwrap_or(&fai);
+        let index = if Self::is_current(fasta_file, &fai, strict, diagnostics)? {
+            fai::read(&fai).with_context(|| format!("could not read {fai}"))?
+        } else if fai_out != fai && Self::is_current(fasta_file, fai_out, strict, diagnostics)? {
+            fai::read(fai_out).with_context(|| format!("could not read {fai_out}"))?
+        } else {
+            Self::write_fai(fasta_file, compressed, fai_out, threads)?
+        };
+        Ok(fasta::indexed_reader::Builder::default()
+            .set_index(index)
+            .build_from_path(fasta_file)?)
+    }
+
+    // Check whether an index of a FASTA file exists and is current. An index
+    // older than the FASTA file may not match it (e.g., if the FASTA file was
+    // edited after it was indexed), so it's rebuilt with a warning or, in
+    // strict mode, is an error. Modification times that can't be read are
+    // assumed to be current.
+    fn is_current(
+        fasta_file: &str,
+        index_file: &str,
+        strict: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<bool> {
+        if !Path::new(index_file).exists() {
+            return Ok(false);
+        }
+        let modified = |path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        match (modified(index_file), modified(fasta_file)) {
+            (Some(indexed), Some(modified)) if indexed < modified => {
+                if strict {
+                    bail!("{index_file} is older than {fasta_file} (re-index it with `extract index`)");
+                }
+                diagnostics.push(Diagnostic::Warning(format!(
+                    "{index_file} is older than {fasta_file}, rebuilding it"
+                )));
+                Ok(false)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    // Read a FASTA file (or stdin, for "-") from the start without an index,
+    // keeping only the named contigs and stopping once all of them have been
+    // read. The kept contigs are queried from memory, so nothing is written
+    // next to the FASTA file, but contigs near the end of a large file take
+    // longer to reach than they would to index.
+    fn scan_reader(
+        fasta_file: &str,
+        names: &[&str],
+    ) -> Result<IndexedReader<Box<dyn BufReadSeek>>> {
+        let input: Box<dyn BufRead> = if fasta_file == "-" {
+            Box::new(io::stdin().lock())
+        } else if is_url(fasta_file) {
+            bail!("FASTA files at URLs are read with their index, so not with --no-index");
+        } else {
+            let file =
+                File::open(fasta_file).with_context(|| format!("could not open {fasta_file}"))?;
+            match Path::new(fasta_file)
+                .extension()
+                .and_then(|ext| ext.to_str())
+            {
+                Some("gz" | "bgz") => Box::new(bgzf::Reader::new(file)),
+                _ => Box::new(BufReader::new(file)),
+            }
+        };
+        let mut reader = fasta::Reader::new(input);
+        let mut remaining: Vec<&str> = names.to_vec();
+        let mut data = Vec::new();
+        let mut writer = fasta::writer::Builder::default().build_with_writer(&mut data);
+        for record in reader.records() {
+            if remaining.is_empty() {
+                break;
+            }
+            let record = record.with_context(|| format!("could not read {fasta_file}"))?;
+            if let Some(i) = remaining.iter().position(|name| *name == record.name()) {
+                remaining.swap_remove(i);
+                writer.write_record(&record)?;
+            }
+        }
+
+        let index = index::index_reader(&data[..])?;
+        let reader: Box<dyn BufReadSeek> = Box::new(Cursor::new(data));
+        Ok(fasta::indexed_reader::Builder::default()
+            .set_index(index)
+            .build_from_reader(reader)?)
+    }
+
+    // Index a FASTA file, optionally bgzipped, and write the index to a .fai
+    // file, using the given number of threads.
+    fn write_fai(
+        fasta_file: &str,
+        compressed: bool,
+        fai_file: &str,
+        threads: usize,
+    ) -> Result<fai::Index> {
+        // The FASTA file is indexed before the .fai is created, so a FASTA
+        // file that can't be indexed doesn't leave an empty index behind.
+        // Plaintext files are indexed in parallel with multiple threads.
+        let index = if compressed {
+            index::index_reader(bgzf::Reader::new(File::open(fasta_file)?))?
+        } else if threads > 1 {
+            let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+            pool.install(|| index::index_parallel(fasta_file, threads))?
+        } else {
+            fasta::index(fasta_file)?
+        };
+        let file =
+            File::create(fai_file).with_context(|| format!("could not create {fai_file}"))?;
+        let mut writer = fai::Writer::new(file);
+        writer.write_index(&index)?;
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::sequences::tests::write_fasta;
+
+    // Set a file's modification time to an hour ago.
+    fn backdate(path: &str) {
+        let file = File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(3600))
+            .unwrap();
+    }
+
+    #[test]
+    fn an_index_older_than_the_fasta_is_rebuilt() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGT\n");
+        Sequences::index_fasta(&fasta).unwrap();
+        fs::write(&fasta, ">chr1\nACGTACGT\n>chr2\nGG\n").unwrap();
+        let fai = format!("{fasta}.fai");
+        backdate(&fai);
+
+        let error = Sequences::get_reader(&fasta, true, None, None, 1, &mut Vec::new())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("is older than"));
+
+        let mut diagnostics = Vec::new();
+        let lengths = Sequences::contig_lengths(&fasta, &mut diagnostics).unwrap();
+        assert_eq!(
+            lengths,
+            [(String::from("chr1"), 8), (String::from("chr2"), 2)]
+        );
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::Warning(format!(
+                "{fai} is older than {fasta}, rebuilding it"
+            ))]
+        );
+
+        // The rebuilt index is current.
+        let mut diagnostics = Vec::new();
+        Sequences::contig_lengths(&fasta, &mut diagnostics).unwrap();
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn a_current_index_at_fai_out_is_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let fasta = write_fasta(&dir, ">chr1\nACGT\n");
+        let fai_out = dir.path().join("out.fai").to_string_lossy().into_owned();
+        let modified = |path: &str| fs::metadata(path).unwrap().modified().unwrap();
+
+        Sequences::get_reader(&fasta, true, None, Some(&fai_out), 1, &mut Vec::new()).unwrap();
+        assert!(!Path::new(&format!("{fasta}.fai")).exists());
+
+        // Date the index ahead so a rewrite would be seen as a change.
+        let ahead = SystemTime::now() + Duration::from_secs(3600);
+        File::options()
+            .write(true)
+            .open(&fai_out)
+            .unwrap()
+            .set_modified(ahead)
+            .unwrap();
+        let mut diagnostics = Vec::new();
+        Sequences::get_reader(&fasta, true, None, Some(&fai_out), 1, &mut diagnostics).unwrap();
+        assert_eq!(modified(&fai_out), ahead);
+        assert!(diagnostics.is_empty());
+
+        // A stale one is rebuilt.
+        backdate(&fai_out);
+        Sequences::get_reader(&fasta, false, None, Some(&fai_out), 1, &mut diagnostics).unwrap();
+        assert!(modified(&fai_out) >= modified(&fasta));
+    }
+}
@@ -0,0 +1,816 @@
+// Reading regions to extract (regions files, features, variants, and
+// alignments) and the other files of the extract options.
+
+use std::{
+    collections::HashMap,
+    fs::{self, read_to_string, File},
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use flate2::read::MultiGzDecoder;
+use noodles::{
+    bgzf,
+    core::{Position, Region},
+    gff, gtf, vcf,
+};
+
+#[cfg(feature = "bam")]
+use crate::bam;
+use crate::{consensus::Variant, Diagnostic};
+
+use super::{Blocks, Sequences};
+
+// The format of the regions file.
+#[derive(Clone, Copy)]
+pub enum RegionFormat {
+    // SAMtools region strings (chr1:1-1000, chr1)
+    Samtools,
+    // tab-delimited BED (chrom, 0-based start, exclusive end, name, score, strand), or BED12,
+    // whose blocks are spliced
+    Bed,
+}
+
+// How the coordinates of SAMtools-style regions are numbered.
+#[derive(Clone, Copy, Default)]
+pub enum Coordinates {
+    // 0-based, half-open (chr1:0-100 is the first 100 bases), as in BED files
+    ZeroBased,
+    // 1-based, inclusive (chr1:1-100 is the first 100 bases), as in SAMtools
+    #[default]
+    OneBased,
+}
+
+// Regions as parsed, with the blocks of any BED12 regions.
+type ParsedRegions = (Vec<(Region, bool, Option<String>)>, Blocks);
+
+impl Sequences {
+    // Read a region for each feature of a type (e.g., exon) in a GFF3 file,
+    // or a GTF file if it ends in .gtf. Features on the reverse strand are
+    // reverse complemented, and GFF3 features are named by their ID.
+    pub fn read_features(
+        feature_file: &str,
+        feature_type: &str,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        let reader = BufReader::new(
+            File::open(feature_file).with_context(|| format!("could not open {feature_file}"))?,
+        );
+        let mut regions = Vec::new();
+        if let Some("gtf") = Path::new(feature_file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+        {
+            for record in gtf::Reader::new(reader).records() {
+                let record = record.with_context(|| format!("could not read {feature_file}"))?;
+                if record.ty() == feature_type {
+                    let region = Region::new(
+                        record.reference_sequence_name(),
+                        record.start()..=record.end(),
+                    );
+                    let reversed = record.strand() == Some(gtf::record::Strand::Reverse);
+                    regions.push((region, reversed, None));
+                }
+            }
+        } else {
+            for record in gff::Reader::new(reader).records() {
+                let record = record.with_context(|| format!("could not read {feature_file}"))?;
+                if record.ty() == feature_type {
+                    let region = Region::new(
+                        record.reference_sequence_name(),
+                        record.start()..=record.end(),
+                    );
+                    let reversed = record.strand() == gff::record::Strand::Reverse;
+                    let name = record
+                        .attributes()
+                        .get("ID")
+                        .and_then(|id| id.as_string())
+                        .map(String::from);
+                    regions.push((region, reversed, name));
+                }
+            }
+        }
+        Ok(regions)
+    }
+
+    // Read a region for each variant in a VCF file (bgzipped if it ends in
+    // .gz/.bgz), covering the variant's reference bases and named by its
+    // first ID, if it has one.
+    pub fn read_variants(vcf_file: &str) -> Result<Vec<(Region, bool, Option<String>)>> {
+        let mut regions = Vec::new();
+        for (chromosome, record) in Self::read_vcf(vcf_file)? {
+            let start = usize::from(record.position());
+            let end = start + record.reference_bases().len() - 1;
+            let interval = Position::try_from(start)
+                .and_then(|start| Ok(start..=Position::try_from(end)?))
+                .with_context(|| format!("{vcf_file}: invalid position for {chromosome}"))?;
+            let name = record.ids().first().map(|id| id.to_string());
+            regions.push((Region::new(chromosome.as_str(), interval), false, name));
+        }
+        Ok(regions)
+    }
+
+    // Read a region for each mapped alignment in a SAM or BAM file, covering
+    // the reference it spans (and its soft clips, if requested), named by
+    // its read name.
+    #[cfg_attr(not(feature = "bam"), allow(unused_variables))]
+    pub fn read_alignments(
+        bam_file: &str,
+        soft_clips: bool,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        #[cfg(feature = "bam")]
+        return bam::read_alignments(bam_file, soft_clips);
+        #[cfg(not(feature = "bam"))]
+        bail!("reading SAM/BAM files needs the bam feature: {bam_file}");
+    }
+
+    // Read the variants to apply to extracted sequences from a VCF file,
+    // by contig and sorted by position. Only the first alternate allele of
+    // each variant is applied, and variants whose first alternate allele
+    // isn't a list of bases (e.g., <DEL> or *) are skipped.
+    pub fn read_consensus(vcf_file: &str) -> Result<HashMap<String, Vec<Variant>>> {
+        let mut variants: HashMap<String, Vec<Variant>> = HashMap::new();
+        for (chromosome, record) in Self::read_vcf(vcf_file)? {
+            let Some(allele @ vcf::record::alternate_bases::Allele::Bases(_)) =
+                record.alternate_bases().first()
+            else {
+                continue;
+            };
+            variants.entry(chromosome).or_default().push(Variant {
+                position: usize::from(record.position()),
+                reference: record.reference_bases().to_string().into_bytes(),
+                alternate: allele.to_string().into_bytes(),
+            });
+        }
+        for contig_variants in variants.values_mut() {
+            contig_variants.sort_by_key(|variant| variant.position);
+        }
+        Ok(variants)
+    }
+
+    // Read the records of a VCF file (bgzipped if it ends in .gz/.bgz) with
+    // the name of each record's chromosome.
+    fn read_vcf(vcf_file: &str) -> Result<Vec<(String, vcf::Record)>> {
+        let file = File::open(vcf_file).with_context(|| format!("could not open {vcf_file}"))?;
+        let reader: Box<dyn BufRead> =
+            match Path::new(vcf_file).extension().and_then(|ext| ext.to_str()) {
+                Some("gz" | "bgz") => Box::new(bgzf::Reader::new(file)),
+                _ => Box::new(BufReader::new(file)),
+            };
+        let mut reader = vcf::Reader::new(reader);
+        let header = reader
+            .read_header()
+            .with_context(|| format!("could not read {vcf_file}"))?;
+        let mut records = Vec::new();
+        for record in reader.records(&header) {
+            let record = record.with_context(|| format!("could not read {vcf_file}"))?;
+            let vcf::record::Chromosome::Name(chromosome) = record.chromosome() else {
+                bail!(
+                    "{vcf_file}: variant at {} is on a symbolic chromosome",
+                    record.chromosome()
+                );
+            };
+            records.push((chromosome.clone(), record));
+        }
+        Ok(records)
+    }
+
+    // Read a name map: a TSV of names (e.g., gene symbols) and the regions
+    // they stand for, in the same format as lines of a regions file.
+    pub fn read_name_map(name_map_file: &str) -> Result<HashMap<String, (Region, bool)>> {
+        let contents = read_to_string(name_map_file)
+            .with_context(|| format!("could not read {name_map_file}"))?;
+        let mut name_map = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((name, region)) = line.split_once('\t') else {
+                bail!(
+                    "{name_map_file}: line {}: expected a name and a region",
+                    i + 1
+                );
+            };
+            let (region, reversed, _) = Self::parse_region(region, Coordinates::OneBased)
+                .with_context(|| format!("{name_map_file}: line {}", i + 1))?;
+            name_map.insert(name.trim().to_string(), (region, reversed));
+        }
+        Ok(name_map)
+    }
+
+    // Read a complement table: lines of a base and its complement separated
+    // by whitespace (e.g., "M H" for a modified base), ignoring blank lines
+    // and comments starting with #. Bases are single ASCII characters and
+    // are matched exactly, so a lowercase base is listed separately.
+    pub fn read_complement_table(table_file: &str) -> Result<HashMap<u8, u8>> {
+        let contents =
+            read_to_string(table_file).with_context(|| format!("could not read {table_file}"))?;
+        let mut table = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let &[base, complement] = &fields[..] else {
+                bail!(
+                    "{table_file}: line {}: expected a base and its complement",
+                    i + 1
+                );
+            };
+            let [base, complement] = [base, complement].map(|field| match field.as_bytes() {
+                &[c] if c.is_ascii_graphic() => Some(c),
+                _ => None,
+            });
+            let (Some(base), Some(complement)) = (base, complement) else {
+                bail!(
+                    "{table_file}: line {}: bases must be single ASCII characters",
+                    i + 1
+                );
+            };
+            if table.insert(base, complement).is_some() {
+                bail!(
+                    "{table_file}: line {}: {} is listed more than once",
+                    i + 1,
+                    char::from(base)
+                );
+            }
+        }
+        Ok(table)
+    }
+
+    // Read a contig alias file: lines of names that a contig goes by (e.g.,
+    // "chr1 1" or "chrM MT M"), separated by whitespace, ignoring blank lines
+    // and comments starting with #. Each name is an alias of every other
+    // name on its line, in the order listed, so one file maps either way.
+    pub fn read_chr_alias(alias_file: &str) -> Result<HashMap<String, Vec<String>>> {
+        let contents =
+            read_to_string(alias_file).with_context(|| format!("could not read {alias_file}"))?;
+        let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let names: Vec<&str> = line.split_whitespace().collect();
+            if names.len() < 2 {
+                bail!(
+                    "{alias_file}: line {}: expected a name and at least one alias",
+                    i + 1
+                );
+            }
+            for name in &names {
+                let others = names.iter().filter(|other| *other != name);
+                aliases
+                    .entry(name.to_string())
+                    .or_default()
+                    .extend(others.map(|other| other.to_string()));
+            }
+        }
+        Ok(aliases)
+    }
+
+    // Read a file of contig MD5 digests: lines of a contig name and its
+    // digest separated by whitespace (e.g., the SN and M5 of a SAM header's
+    // @SQ lines), ignoring blank lines and comments starting with #. The
+    // digests are returned with the contig each one names.
+    pub fn read_contig_md5(md5_file: &str) -> Result<HashMap<String, String>> {
+        let contents =
+            read_to_string(md5_file).with_context(|| format!("could not read {md5_file}"))?;
+        let mut digests = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let &[name, digest] = &fields[..] else {
+                bail!(
+                    "{md5_file}: line {}: expected a contig name and its MD5 digest",
+                    i + 1
+                );
+            };
+            let Some(digest) = Self::digest_of(&format!("md5:{digest}")) else {
+                bail!("{md5_file}: line {}: invalid MD5 digest {digest}", i + 1);
+            };
+            digests.insert(digest, name.to_string());
+        }
+        Ok(digests)
+    }
+
+    // Read an order file: record names, one per line, in the order to write
+    // them, ranked by their first appearance.
+    pub fn read_order(order_file: &str) -> Result<HashMap<String, usize>> {
+        let contents =
+            read_to_string(order_file).with_context(|| format!("could not read {order_file}"))?;
+        let mut order = HashMap::new();
+        for name in contents
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let rank = order.len();
+            order.entry(name.to_string()).or_insert(rank);
+        }
+        Ok(order)
+    }
+
+    // Parse the regions files, concatenating their regions in the order
+    // given.
+    pub fn read_regions(
+        region_files: &[String],
+        region_format: Option<RegionFormat>,
+        coords: Coordinates,
+        strict: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<Vec<(Region, bool, Option<String>)>> {
+        let (regions, _) = Self::read_regions_and_blocks(
+            region_files,
+            region_format,
+            coords,
+            strict,
+            diagnostics,
+        )?;
+        Ok(regions)
+    }
+
+    // Parse the regions files, as for read_regions, with the blocks of any
+    // BED12 regions. Two BED12 lines with the same region and name must
+    // have the same blocks.
+    pub(super) fn read_regions_and_blocks(
+        region_files: &[String],
+        region_format: Option<RegionFormat>,
+        coords: Coordinates,
+        strict: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<ParsedRegions> {
+        let mut regions = Vec::new();
+        let mut blocks: Blocks = HashMap::new();
+        for region_file in region_files {
+            let (file_regions, file_blocks) =
+                Self::get_regions(region_file, region_format, coords, strict, diagnostics)?;
+            regions.extend(file_regions);
+            for (key, region_blocks) in file_blocks {
+                match blocks.get(&key) {
+                    Some(existing) if *existing != region_blocks => {
+                        bail!("{region_file}: region {} has different BED12 blocks on different lines", key.0);
+                    }
+                    _ => {
+                        blocks.insert(key, region_blocks);
+                    }
+                }
+            }
+        }
+        Ok((regions, blocks))
+    }
+
+    // The key of a region's blocks: its string and name.
+    pub(super) fn block_key(region: &Region, name: &Option<String>) -> (String, Option<String>) {
+        (region.to_string(), name.clone())
+    }
+
+    // Parse each non-blank line in the regions file, noting whether
+    // it should be reverse complemented and what the output record should
+    // be named (BED only). Files ending in .bed are read as BED unless a
+    // format is given. Lines that fail to parse are reported with their
+    // line number, as warnings or, in strict mode, as a single error.
+    // SAMtools-style coordinates are 1-based unless 0-based coordinates are
+    // given; BED coordinates are always 0-based. The blocks of BED12 lines
+    // with more than one block are returned alongside the regions. Gzipped
+    // regions files (or stdin) are decompressed, and the format of a file
+    // ending in .bed.gz is detected as for .bed.
+    fn get_regions(
+        region_file: &str,
+        region_format: Option<RegionFormat>,
+        coords: Coordinates,
+        strict: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Result<ParsedRegions> {
+        let region_format = region_format.unwrap_or_else(|| {
+            let uncompressed = region_file
+                .strip_suffix(".gz")
+                .or_else(|| region_file.strip_suffix(".bgz"))
+                .unwrap_or(region_file);
+            match Path::new(uncompressed)
+                .extension()
+                .and_then(|ext| ext.to_str())
+            {
+                Some("bed") => RegionFormat::Bed,
+                _ => RegionFormat::Samtools,
+            }
+        });
+
+        // A regions file of "-" is read from stdin. Gzipped (or bgzipped)
+        // contents are recognized by their magic bytes, whatever the name.
+        let mut data = Vec::new();
+        if region_file == "-" {
+            io::stdin().read_to_end(&mut data)?;
+        } else {
+            data =
+                fs::read(region_file).with_context(|| format!("could not read {region_file}"))?;
+        }
+        if data.starts_with(&[0x1f, 0x8b]) {
+            let mut decompressed = Vec::new();
+            MultiGzDecoder::new(&data[..])
+                .read_to_end(&mut decompressed)
+                .with_context(|| format!("could not decompress {region_file}"))?;
+            data = decompressed;
+        }
+        let contents = String::from_utf8(data)
+            .with_context(|| format!("could not read {region_file}: not valid UTF-8"))?;
+
+        let mut regions = Vec::new();
+        let mut blocks: Blocks = HashMap::new();
+        let mut invalid = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let region = match region_format {
+                RegionFormat::Samtools => Self::parse_region(line, coords).map(|r| (r, None)),
+                RegionFormat::Bed if Self::is_bed_header(line) => continue,
+                RegionFormat::Bed => Self::parse_bed_line(line)
+                    .and_then(|region| Ok((region, Self::parse_bed_blocks(line)?))),
+            };
+            match region {
+                Ok((region, None)) => regions.push(region),
+                Ok(((region, reversed, name), Some(region_blocks))) => {
+                    let key = Self::block_key(&region, &name);
+                    match blocks.get(&key) {
+                        Some(existing) if *existing != region_blocks => invalid.push(format!(
+                            "line {}: {line} (different blocks than an earlier line for the same region)",
+                            i + 1
+                        )),
+                        _ => {
+                            blocks.insert(key, region_blocks);
+                            regions.push((region, reversed, name));
+                        }
+                    }
+                }
+                Err(e) => invalid.push(format!("line {}: {line} ({e})", i + 1)),
+            }
+        }
+
+        if !invalid.is_empty() {
+            if strict {
+                bail!("invalid regions in {region_file}:\n{}", invalid.join("\n"));
+            }
+            for invalid in invalid {
+                diagnostics.push(Diagnostic::Warning(format!(
+                    "skipping invalid region on {invalid}"
+                )));
+            }
+        }
+        Ok((regions, blocks))
+    }
+
+    // Parse a SAMtools-style region, where a leading '-' requests the
+    // reverse complement. A strand suffix (:+ or :-) can be given instead,
+    // and takes precedence over the prefix, and a strand after the region
+    // and whitespace (+, -, fwd, or rev, e.g., chr1:100-200 rev) takes
+    // precedence over both. Regions pasted from genome
+    // browsers may have surrounding whitespace and thousands separators in
+    // the coordinates (e.g., chr1:1,000-2,000), which are removed. The
+    // region can be preceded by an output name and '=' (e.g.,
+    // promoter=-chr1:100-200), splitting on the first '='.
+    pub(crate) fn parse_region(
+        region: &str,
+        coords: Coordinates,
+    ) -> Result<(Region, bool, Option<String>)> {
+        let mut reverse = false;
+        let mut region = region.trim();
+        let mut output_name = None;
+        // The = of first=N or last=N coordinates (e.g., chr1:first=1000)
+        // doesn't follow a name.
+        let named = region.split_once('=').filter(|(name, _)| {
+            !name
+                .rsplit_once(':')
+                .is_some_and(|(_, end)| matches!(end.trim(), "first" | "last"))
+        });
+        if let Some((name, rest)) = named {
+            let name = name.trim();
+            if name.is_empty() {
+                bail!("empty name before '='");
+            }
+            output_name = Some(name.to_string());
+            region = rest.trim_start();
+        }
+        let mut strand = None;
+        if let Some((rest, token)) = region.rsplit_once(char::is_whitespace) {
+            strand = match token {
+                "+" | "fwd" => Some(false),
+                "-" | "rev" => Some(true),
+                _ => None,
+            };
+            if strand.is_some() {
+                region = rest.trim_end();
+            }
+        }
+        let mut region = region.to_string();
+        if region.starts_with('-') {
+            reverse = true;
+            region = region[1..].trim_start().to_string();
+        }
+        if let Some(stripped) = region.strip_suffix(":+") {
+            reverse = false;
+            region = stripped.to_string();
+        } else if let Some(stripped) = region.strip_suffix(":-") {
+            reverse = true;
+            region = stripped.to_string();
+        }
+        if let Some(strand) = strand {
+            reverse = strand;
+        }
+
+        // A whole contig given by digest would otherwise parse as a contig
+        // named md5 with the digest as its interval.
+        if Self::digest_of(&region).is_some() {
+            return Ok((Region::new(region, ..), reverse, output_name));
+        }
+        if let Some((name, coordinates)) = region.rsplit_once(':') {
+            // First or last N bases are kept in the name, as relative
+            // coordinates are.
+            let compact: String = coordinates.split_whitespace().collect();
+            if Self::first_or_last(&compact).is_some() {
+                let region = Region::new(format!("{}:{compact}", name.trim_end()), ..);
+                return Ok((region, reverse, output_name));
+            }
+            let coordinates: String = coordinates
+                .chars()
+                .filter(|&c| c != ',' && !c.is_whitespace())
+                .collect();
+
+            // Relative coordinates (e.g., 10%-90%) are kept in the name until
+            // they're resolved with the contig's length. A 0-based absolute
+            // start is still shifted, as it is for absolute regions.
+            if coordinates.contains('%')
+                && coordinates
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || matches!(c, '-' | '%' | '.'))
+            {
+                let coordinates = match (coords, coordinates.split_once('-')) {
+                    (Coordinates::ZeroBased, Some((start, end))) if !start.ends_with('%') => {
+                        let start = start.parse::<usize>().context("invalid start")? + 1;
+                        format!("{start}-{end}")
+                    }
+                    _ => coordinates,
+                };
+                let region = Region::new(format!("{}:{coordinates}", name.trim_end()), ..);
+                return Ok((region, reverse, output_name));
+            }
+            if coordinates.chars().all(|c| c.is_ascii_digit() || c == '-') {
+                let coordinates = match coords {
+                    Coordinates::ZeroBased => Self::to_one_based(&coordinates)?,
+                    Coordinates::OneBased => coordinates,
+                };
+                let pattern = Self::is_pattern(name);
+                region = format!("{}:{coordinates}", name.trim_end());
+
+                // A contig pattern's coordinates are kept in the name until
+                // the pattern is expanded, since a contig may be named like
+                // the whole region (e.g., HLA-A*01:01:01:01).
+                if pattern {
+                    return Ok((Region::new(region, ..), reverse, output_name));
+                }
+            }
+        }
+
+        let region: Region = region.parse()?;
+        Ok((region, reverse, output_name))
+    }
+
+    // Convert 0-based, half-open coordinates (start-end or start) to 1-based,
+    // inclusive ones by shifting the start, as for BED lines (e.g., 0-100
+    // becomes 1-100).
+    fn to_one_based(coordinates: &str) -> Result<String> {
+        let (start, end) = match coordinates.split_once('-') {
+            Some((start, end)) => (start, Some(end)),
+            None => (coordinates, None),
+        };
+        let start = start.parse::<usize>().context("invalid start")? + 1;
+        Ok(match end {
+            Some(end) => {
+                let end = end.parse::<usize>().context("invalid end")?;
+                if end < start {
+                    bail!("0-based region is empty");
+                }
+                format!("{start}-{end}")
+            }
+            None => start.to_string(),
+        })
+    }
+
+    // BED files may begin with comments, track and browser lines.
+    fn is_bed_header(line: &str) -> bool {
+        line.starts_with('#') || line.starts_with("track") || line.starts_with("browser")
+    }
+
+    // Parse a BED line (chrom, start, end, and optionally name, score and
+    // strand). BED starts are 0-based and ends are exclusive, so the start
+    // is shifted by one to get the 1-based inclusive interval noodles uses.
+    // A zero-length interval (start equal to end) is invalid, since it would
+    // otherwise start after it ends and wrap around a circular contig.
+    fn parse_bed_line(line: &str) -> Result<(Region, bool, Option<String>)> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 3 {
+            bail!("expected at least 3 tab-delimited fields");
+        }
+        let start = fields[1].parse::<usize>().context("invalid start")?;
+        let end = fields[2].parse::<usize>().context("invalid end")?;
+        if start > end {
+            bail!("start is after end");
+        }
+        if start == end {
+            bail!("zero-length interval");
+        }
+        let start = Position::try_from(start + 1)?;
+        let end = Position::try_from(end).context("invalid end")?;
+
+        let name = fields
+            .get(3)
+            .filter(|name| !name.is_empty() && **name != ".")
+            .map(|name| name.to_string());
+        let reverse = fields.get(5) == Some(&"-");
+
+        Ok((Region::new(fields[0], start..=end), reverse, name))
+    }
+
+    // Parse the blocks (e.g., exons) of a BED12 line from its block count,
+    // sizes, and starts (relative to the line's start), as 1-based regions
+    // in genomic order, as for `bedtools getfasta -split`. Lines with fewer
+    // than 12 fields or a single block have no blocks to splice. Blocks must
+    // be in order, not overlap, and end by the line's end.
+    fn parse_bed_blocks(line: &str) -> Result<Option<Vec<Region>>> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() < 12 {
+            return Ok(None);
+        }
+        let start = fields[1].parse::<usize>().context("invalid start")?;
+        let end = fields[2].parse::<usize>().context("invalid end")?;
+        let count = fields[9].parse::<usize>().context("invalid block count")?;
+        let list = |field: &str, what: &str| -> Result<Vec<usize>> {
+            field
+                .split(',')
+                .filter(|value| !value.is_empty())
+                .map(|value| {
+                    value
+                        .parse::<usize>()
+                        .with_context(|| format!("invalid {what}"))
+                })
+                .collect()
+        };
+        let sizes = list(fields[10], "block size")?;
+        let starts = list(fields[11], "block start")?;
+        if sizes.len() != count || starts.len() != count {
+            bail!("expected {count} block sizes and starts");
+        }
+        if count <= 1 {
+            return Ok(None);
+        }
+
+        let mut blocks = Vec::with_capacity(count);
+        let mut previous_end = start;
+        for (size, block_start) in sizes.into_iter().zip(starts) {
+            let block_start = start + block_start;
+            let block_end = block_start + size;
+            if block_start < previous_end || block_end > end || size == 0 {
+                bail!("blocks are empty, out of order, overlapping, or past the end");
+            }
+            let interval = Position::try_from(block_start + 1)?..=Position::try_from(block_end)?;
+            blocks.push(Region::new(fields[0], interval));
+            previous_end = block_end;
+        }
+        Ok(Some(blocks))
+    }
+
+    // Split first=N or last=N coordinates into the end of the contig and N.
+    pub(super) fn first_or_last(coordinates: &str) -> Option<(&str, &str)> {
+        coordinates
+            .split_once('=')
+            .filter(|(end, _)| matches!(*end, "first" | "last"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    // Write a regions file to a temporary directory, returning its path.
+    fn regions_file(dir: &tempfile::TempDir, name: &str, contents: &str) -> String {
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn zero_length_bed_intervals_are_skipped_with_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let bed = regions_file(&dir, "r.bed", "chr1\t10\t10\tempty\nchr1\t10\t20\tkept\n");
+        let mut diagnostics = Vec::new();
+        let regions = Sequences::read_regions(
+            &[bed],
+            None,
+            Coordinates::default(),
+            false,
+            &mut diagnostics,
+        )
+        .unwrap();
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions[0].0.to_string(), "chr1:11-20");
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::Warning(String::from(
+                "skipping invalid region on line 1: chr1\t10\t10\tempty (zero-length interval)"
+            ))]
+        );
+    }
+
+    #[test]
+    fn zero_length_bed_intervals_are_an_error_when_strict() {
+        let dir = tempfile::tempdir().unwrap();
+        let bed = regions_file(&dir, "r.bed", "chr1\t0\t0\n");
+        let error =
+            Sequences::read_regions(&[bed], None, Coordinates::default(), true, &mut Vec::new())
+                .unwrap_err();
+        assert!(error.to_string().contains("zero-length interval"));
+    }
+
+    #[test]
+    fn thousands_separators_and_whitespace_are_removed() {
+        for (line, expected, reversed) in [
+            ("chr1:1,000-2,000", "chr1:1000-2000", false),
+            ("  chr1:1,000-2,000\t", "chr1:1000-2000", false),
+            ("-chr1:1,000-2,000", "chr1:1000-2000", true),
+            ("chr1:1,000,000-1,000,500:-", "chr1:1000000-1000500", true),
+            ("chr1:2,500", "chr1:2500", false),
+        ] {
+            let (region, is_reversed, _) =
+                Sequences::parse_region(line, Coordinates::OneBased).unwrap();
+            assert_eq!(region.to_string(), expected, "{line}");
+            assert_eq!(is_reversed, reversed, "{line}");
+        }
+        assert!(Sequences::parse_region("chr1:1,0x0-2,000", Coordinates::OneBased).is_err());
+    }
+
+    #[test]
+    fn zero_length_regions_are_an_error() {
+        let error = Sequences::parse_region("chr1:5-5", Coordinates::ZeroBased).unwrap_err();
+        assert_eq!(error.to_string(), "0-based region is empty");
+    }
+
+    #[test]
+    fn zero_based_and_one_based_regions_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let zero_based = regions_file(&dir, "zero.txt", "chr1:0-100\n-chr2:99-200\nchr3\n");
+        let one_based = regions_file(&dir, "one.txt", "chr1:1-100\n-chr2:100-200\nchr3\n");
+        let bed = regions_file(&dir, "r.bed", "chr1\t0\t100\nchr2\t99\t200\t.\t0\t-\n");
+        let read = |file: &str, coords| {
+            Sequences::read_regions(&[file.to_string()], None, coords, true, &mut Vec::new())
+                .unwrap()
+        };
+        let regions = read(&one_based, Coordinates::OneBased);
+        assert_eq!(read(&zero_based, Coordinates::ZeroBased), regions);
+        assert_eq!(read(&bed, Coordinates::OneBased), regions[..2]);
+        assert_eq!(read(&bed, Coordinates::ZeroBased), regions[..2]);
+    }
+
+    #[test]
+    fn gzipped_regions_files_match_their_plaintext() {
+        use std::io::Write;
+
+        use flate2::{write::GzEncoder, Compression};
+
+        let dir = tempfile::tempdir().unwrap();
+        let read = |file: String| {
+            Sequences::read_regions(&[file], None, Coordinates::default(), true, &mut Vec::new())
+                .unwrap()
+        };
+        for (name, contents) in [
+            (
+                "r.bed",
+                "chr1\t0\t100\tfirst\nchr2\t99\t200\tsecond\t0\t-\n",
+            ),
+            ("r.txt", "chr1:1-100\n-chr2:100-200\nchr3\n"),
+        ] {
+            let plain = regions_file(&dir, name, contents);
+
+            let gzipped = dir.path().join(format!("{name}.gz"));
+            let mut encoder =
+                GzEncoder::new(File::create(&gzipped).unwrap(), Compression::default());
+            encoder.write_all(contents.as_bytes()).unwrap();
+            encoder.finish().unwrap();
+
+            let bgzipped = dir.path().join(format!("{name}.bgz"));
+            let mut writer = bgzf::Writer::new(File::create(&bgzipped).unwrap());
+            writer.write_all(contents.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let regions = read(plain);
+            assert!(!regions.is_empty());
+            assert_eq!(read(gzipped.to_string_lossy().into_owned()), regions);
+            assert_eq!(read(bgzipped.to_string_lossy().into_owned()), regions);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fs::{read_to_string, remove_file},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use noodles::fasta;
+
+use crate::{Coordinates, Diagnostic, ExtractOptions, Sequences};
+
+// Responses to queries that fail start with this, which FASTA output never
+// does.
+const ERROR_PREFIX: &str = "error: ";
+
+// Serve region queries for a FASTA file over a Unix socket, keeping the
+// FASTA file and its index open between queries. Each connection sends
+// SAMtools-style regions, one per line, and closes its end for writing; the
+// server answers with the extracted regions as FASTA, or a line starting
+// with "error: " if any region can't be parsed or extracted. Connections
+// are answered one at a time. A socket file left behind by a server that's
+// no longer running is replaced. Warnings and notes (e.g., that the server
+// is listening) are passed to report as they happen.
+pub fn serve(fasta_file: &str, socket: &str, mut report: impl FnMut(Diagnostic)) -> Result<()> {
+    // Extracting no regions opens the FASTA file (indexing it, if needed)
+    // before any queries arrive.
+    let mut sequences = Sequences::from_regions(fasta_file, Vec::new())?;
+    sequences.extract(ExtractOptions::default())?;
+    sequences
+        .take_diagnostics()
+        .into_iter()
+        .for_each(&mut report);
+
+    if Path::new(socket).exists() {
+        if UnixStream::connect(socket).is_ok() {
+            bail!("a server is already listening on {socket}");
+        }
+        remove_file(socket).with_context(|| format!("could not remove {socket}"))?;
+    }
+    let listener =
+        UnixListener::bind(socket).with_context(|| format!("could not bind {socket}"))?;
+    report(Diagnostic::Note(format!(
+        "serving {fasta_file} on {socket}"
+    )));
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                report(Diagnostic::Warning(format!(
+                    "could not accept a connection: {e}"
+                )));
+                continue;
+            }
+        };
+        if let Err(e) = answer(&mut sequences, &stream) {
+            // The client may already be gone, so the error can't always be
+            // sent back.
+            let _ = writeln!(stream, "{ERROR_PREFIX}{e:#}");
+        }
+        sequences
+            .take_diagnostics()
+            .into_iter()
+            .for_each(&mut report);
+    }
+    Ok(())
+}
+
+// Extract the regions of one query and write them to the stream.
+fn answer(sequences: &mut Sequences, stream: &UnixStream) -> Result<()> {
+    let mut regions = Vec::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let region = Sequences::parse_region(&line, Coordinates::OneBased)
+            .with_context(|| format!("invalid region {line}"))?;
+        regions.push(region);
+    }
+    sequences.set_regions(regions);
+    sequences.extract(ExtractOptions::default())?;
+
+    let mut writer = fasta::writer::Builder::default().build_with_writer(stream);
+    for record in sequences.records() {
+        writer.write_record(record)?;
+    }
+    Ok(())
+}
+
+// Send regions from a file (or stdin, for "-") to a server and copy the
+// FASTA it answers with to stdout.
+pub fn query(socket: &str, region_file: &str) -> Result<()> {
+    let regions = if region_file == "-" {
+        let mut regions = String::new();
+        io::stdin().read_to_string(&mut regions)?;
+        regions
+    } else {
+        read_to_string(region_file).with_context(|| format!("could not read {region_file}"))?
+    };
+
+    let mut stream =
+        UnixStream::connect(socket).with_context(|| format!("could not connect to {socket}"))?;
+    stream.write_all(regions.as_bytes())?;
+    stream.shutdown(Shutdown::Write)?;
+
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    if let Some(error) = response.strip_prefix(ERROR_PREFIX.as_bytes()) {
+        bail!("{}", String::from_utf8_lossy(error).trim_end());
+    }
+    io::stdout().lock().write_all(&response)?;
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use noodles::fasta::record::Sequence;
+
+// The standard genetic code, listing amino acids for codons in TCAG order
+// (TTT, TTC, TTA, TTG, TCT, ...). Stop codons are '*'.
+const STANDARD_CODE: &[u8; 64] =
+    b"FFLLSSSSYY**CC*WLLLLPPPPHHQQRRRRIIIMTTTTNNKKSSRRVVVVAAAADDEEGGGG";
+
+static CODON_TABLE: OnceLock<HashMap<[u8; 3], u8>> = OnceLock::new();
+
+// Return the codon table for the standard genetic code.
+pub fn codon_table() -> &'static HashMap<[u8; 3], u8> {
+    CODON_TABLE.get_or_init(|| {
+        const BASES: [u8; 4] = [b'T', b'C', b'A', b'G'];
+        let mut table = HashMap::new();
+        for (i, amino_acid) in STANDARD_CODE.iter().enumerate() {
+            let codon = [BASES[i / 16], BASES[i / 4 % 4], BASES[i % 4]];
+            table.insert(codon, *amino_acid);
+        }
+        table
+    })
+}
+
+// Translate a nucleotide sequence to amino acids, starting at an offset of
+// 0, 1, or 2 bases. Codons are read case-insensitively with U treated as T;
+// codons containing any other base (e.g., N) translate to X, and a trailing
+// partial codon is dropped.
+pub fn translate(sequence: &Sequence, frame: usize) -> Sequence {
+    let table = codon_table();
+    sequence
+        .as_ref()
+        .get(frame..)
+        .unwrap_or_default()
+        .chunks_exact(3)
+        .map(|codon| {
+            let codon =
+                [codon[0], codon[1], codon[2]].map(|base| match base.to_ascii_uppercase() {
+                    b'U' => b'T',
+                    base => base,
+                });
+            table.get(&codon).copied().unwrap_or(b'X')
+        })
+        .collect()
+}
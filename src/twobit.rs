@@ -0,0 +1,175 @@
+use std::{
+    borrow::Cow,
+    io::{self, ErrorKind, Write},
+};
+
+// The 2bit signature, version, and the code for each base (T, C, A, G). N
+// bases are stored as T, with their positions recorded in N blocks.
+const SIGNATURE: u32 = 0x1A41_2743;
+const VERSION: u32 = 0;
+
+fn base_code(base: u8) -> Option<u8> {
+    match base.to_ascii_uppercase() {
+        b'T' | b'N' => Some(0),
+        b'C' => Some(1),
+        b'A' => Some(2),
+        b'G' => Some(3),
+        _ => None,
+    }
+}
+
+// Write named sequences in the UCSC 2bit format: a header and an index of
+// sequence names and offsets, then for each sequence its length, runs of N
+// (N blocks), runs of soft-masked lowercase bases (mask blocks), and its
+// bases packed 4 to a byte. Only A, C, G, T, and N can be represented, so
+// other bases are an error unless `force` is set, which writes them as N.
+pub fn write<W: Write>(
+    mut writer: W,
+    sequences: &[(&str, Cow<[u8]>)],
+    force: bool,
+) -> io::Result<()> {
+    let records = sequences
+        .iter()
+        .map(|(name, sequence)| encode_record(name, sequence, force))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    // Records follow the header and index, in order.
+    let index_size: usize = sequences.iter().map(|(name, _)| 1 + name.len() + 4).sum();
+    let mut offset = 16 + index_size;
+
+    let count = u32::try_from(sequences.len()).map_err(|_| too_large())?;
+    for value in [SIGNATURE, VERSION, count, 0] {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    for ((name, _), record) in sequences.iter().zip(&records) {
+        let name_size = u8::try_from(name.len()).map_err(|_| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{name}: 2bit names can be at most 255 bytes"),
+            )
+        })?;
+        writer.write_all(&[name_size])?;
+        writer.write_all(name.as_bytes())?;
+        let record_offset = u32::try_from(offset).map_err(|_| too_large())?;
+        writer.write_all(&record_offset.to_le_bytes())?;
+        offset += record.len();
+    }
+    for record in records {
+        writer.write_all(&record)?;
+    }
+    Ok(())
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, "2bit output is limited to 4 GiB")
+}
+
+fn encode_record(name: &str, sequence: &[u8], force: bool) -> io::Result<Vec<u8>> {
+    let mut n_blocks = Vec::new();
+    let mut mask_blocks = Vec::new();
+    let mut packed = vec![0; sequence.len().div_ceil(4)];
+
+    for (i, &base) in sequence.iter().enumerate() {
+        let code = base_code(base);
+        if code.is_none() && !force {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "{name}:{}: {} is not A, C, G, T, or N (use --force to write it as N)",
+                    i + 1,
+                    char::from(base)
+                ),
+            ));
+        }
+        let is_n = code.is_none() || base.eq_ignore_ascii_case(&b'N');
+        extend_blocks(&mut n_blocks, i, is_n);
+        extend_blocks(&mut mask_blocks, i, base.is_ascii_lowercase());
+        packed[i / 4] |= code.unwrap_or(0) << (6 - 2 * (i % 4));
+    }
+
+    let mut record =
+        Vec::with_capacity(16 + 8 * (n_blocks.len() + mask_blocks.len()) + packed.len());
+    let length = u32::try_from(sequence.len()).map_err(|_| too_large())?;
+    record.extend_from_slice(&length.to_le_bytes());
+    for blocks in [&n_blocks, &mask_blocks] {
+        record.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        for (start, _) in blocks.iter() {
+            record.extend_from_slice(&start.to_le_bytes());
+        }
+        for (_, size) in blocks.iter() {
+            record.extend_from_slice(&size.to_le_bytes());
+        }
+    }
+    record.extend_from_slice(&0u32.to_le_bytes());
+    record.extend_from_slice(&packed);
+    Ok(record)
+}
+
+// Add position i to a list of (start, size) blocks if it's in a block,
+// extending the last block when i directly follows it.
+fn extend_blocks(blocks: &mut Vec<(u32, u32)>, i: usize, in_block: bool) {
+    if !in_block {
+        return;
+    }
+    let i = i as u32;
+    match blocks.last_mut() {
+        Some((start, size)) if *start + *size == i => *size += 1,
+        _ => blocks.push((i, 1)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Little-endian u32 fields.
+    fn fields(values: &[u32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn sequences_are_packed_with_n_and_mask_blocks() {
+        let mut output = Vec::new();
+        let sequences = [
+            ("s1", Cow::Borrowed(&b"ACgtNNa"[..])),
+            ("s2", Cow::Borrowed(&b"GG"[..])),
+        ];
+        write(&mut output, &sequences, false).unwrap();
+
+        // The header: signature, version, sequence count, and reserved.
+        let mut expected = fields(&[0x1A41_2743, 0, 2, 0]);
+        // The index: each name with its record's offset, after the 16-byte
+        // header and 14 bytes of index.
+        expected.extend(b"\x02s1");
+        expected.extend(fields(&[30]));
+        expected.extend(b"\x02s2");
+        expected.extend(fields(&[30 + 42]));
+        // s1: its length, an N block of 2 at 4, mask blocks of 2 at 2 and 1
+        // at 6, reserved, then A C G T and T T A packed (N is stored as T).
+        expected.extend(fields(&[7, 1, 4, 2, 2, 2, 6, 2, 1, 0]));
+        expected.extend([0b1001_1100, 0b0000_1000]);
+        // s2: no blocks, and G G padded to a byte.
+        expected.extend(fields(&[2, 0, 0, 0]));
+        expected.push(0b1111_0000);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn other_bases_are_an_error_unless_forced() {
+        let sequences = [("s1", Cow::Borrowed(&b"ACRT"[..]))];
+        let error = write(Vec::new(), &sequences, false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "s1:3: R is not A, C, G, T, or N (use --force to write it as N)"
+        );
+
+        let mut output = Vec::new();
+        write(&mut output, &sequences, true).unwrap();
+        let record = &output[16 + 7..];
+        assert_eq!(record[..16], fields(&[4, 1, 2, 1]));
+        assert_eq!(record[record.len() - 1], 0b1001_0000);
+    }
+}